@@ -0,0 +1,404 @@
+//! Hardware addresses and registry prefixes in every common notation.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use crate::error::{Error, Result};
+
+/// The I/G bit of the first octet, set on group addresses.
+const MULTICAST_BIT: u8 = 0x01;
+/// The U/L bit of the first octet, set on addresses that weren't assigned by the IEEE.
+const LOCAL_BIT: u8 = 0x02;
+
+/// A 48 bit hardware address.
+///
+/// Parses `00:1B:21:3A:4F:5C`, `00-1b-21-3a-4f-5c`, Cisco's `001b.213a.4f5c` and bare `001B213A4F5C`, and is
+/// displayed and serialized as upper case octets separated by colons.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct MacAddress(pub [u8; 6]);
+
+/// A registry block or any other leading part of an address, `len` bits long.
+///
+/// Parses the same notations as `MacAddress` with 1 to 12 hex digits, optionally followed by `/len` like Wireshark's
+/// `00:1B:C5:00:00:00/36`. Nibble aligned prefixes are displayed as `00:1B:C5:00:0`, others with the mask.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct OuiPrefix {
+    bits: u64,
+    len: u8,
+}
+
+/// Something wrong with an address that is about to be assigned to an interface.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddressIssue {
+
+    /// `00:00:00:00:00:00`.
+    Zero,
+    /// `FF:FF:FF:FF:FF:FF`.
+    Broadcast,
+    /// The I/G bit is set.
+    Multicast,
+    /// Only a problem for addresses that are supposed to carry a vendor's OUI.
+    LocallyAdministered,
+
+}
+
+impl MacAddress {
+
+    /// The six octets, in transmission order.
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// The address as the low 48 bits of an integer.
+    pub fn to_bits(self) -> u64 {
+        self.0.iter().fold(0, |bits, octet| (bits << 8) | *octet as u64)
+    }
+
+    /// The address held by the low 48 bits of `bits`, the rest is ignored.
+    pub fn from_bits(bits: u64) -> Self {
+        let bytes = bits.to_be_bytes();
+        MacAddress([bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]])
+    }
+
+    /// Whether the I/G bit marks a group address, which includes broadcast.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & MULTICAST_BIT != 0
+    }
+
+    /// Whether this is `FF:FF:FF:FF:FF:FF`.
+    pub fn is_broadcast(&self) -> bool {
+        self.0.iter().all(|octet| *octet == 0xFF)
+    }
+
+    /// Whether this is `00:00:00:00:00:00`.
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|octet| *octet == 0)
+    }
+
+    /// Whether the U/L bit is set, so the address wasn't assigned from a vendor's block.
+    pub fn is_locally_administered(&self) -> bool {
+        self.0[0] & LOCAL_BIT != 0
+    }
+
+    /// Sets the U/L bit and clears the I/G bit, leaving a locally administered unicast address.
+    pub fn into_local_unicast(self) -> Self {
+        let mut octets = self.0;
+        octets[0] = (octets[0] & !MULTICAST_BIT) | LOCAL_BIT;
+        MacAddress(octets)
+    }
+
+    /// Checks the I/G and U/L bits, `vendor` addresses are expected to be universally administered.
+    pub fn issues(&self, vendor: bool) -> Vec<AddressIssue> {
+        let mut issues = Vec::new();
+        if self.is_zero() {
+            issues.push(AddressIssue::Zero);
+        }
+        if self.is_broadcast() {
+            issues.push(AddressIssue::Broadcast);
+        } else if self.is_multicast() {
+            issues.push(AddressIssue::Multicast);
+        }
+        if vendor && self.is_locally_administered() {
+            issues.push(AddressIssue::LocallyAdministered);
+        }
+        return issues;
+    }
+
+}
+
+impl FromStr for MacAddress {
+
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let digits = hex_digits(value);
+        if digits.len() != 12 || !digits.chars().all(|character| character.is_ascii_hexdigit()) {
+            return Err(Error::InvalidMac(format!("Invalid MAC address {}", value)));
+        }
+        return match u64::from_str_radix(&digits, 16) {
+            Ok(bits) => Ok(MacAddress::from_bits(bits)),
+            Err(_) => Err(Error::InvalidMac(format!("Invalid MAC address {}", value)))
+        };
+    }
+
+}
+
+impl fmt::Display for MacAddress {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let octets = self.0;
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", octets[0], octets[1], octets[2], octets[3], octets[4], octets[5])
+    }
+
+}
+
+impl OuiPrefix {
+
+    /// The first `len` bits of an address, `bits` holds them right aligned.
+    pub fn new(bits: u64, len: u8) -> Result<Self> {
+        if len == 0 || len > 48 {
+            return Err(Error::InvalidMac(format!("Invalid prefix length {}", len)));
+        }
+        if bits >> len != 0 {
+            return Err(Error::InvalidMac(format!("Prefix {:X} is longer than {} bits", bits, len)));
+        }
+        return Ok(Self { bits, len });
+    }
+
+    /// The prefix right aligned in an integer, `00:1B:21` is `0x001B21`.
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// Length of the prefix in bits, 24 for an OUI and 48 for a whole address.
+    pub fn length(&self) -> u8 {
+        self.len
+    }
+
+    /// The first `len` bits of this prefix, or the prefix itself if it isn't longer.
+    pub fn truncate(&self, len: u8) -> Self {
+        if len == 0 || len >= self.len {
+            return *self;
+        }
+        Self { bits: self.bits >> (self.len - len), len }
+    }
+
+    /// The first octet, which holds the I/G and U/L bits, if the prefix is long enough.
+    pub fn first_octet(&self) -> Option<u8> {
+        if self.len < 8 {
+            return None;
+        }
+        Some((self.bits >> (self.len - 8)) as u8)
+    }
+
+    /// Whether addresses of this block are group addresses, false if the prefix is shorter than an octet.
+    pub fn is_multicast(&self) -> bool {
+        self.first_octet().is_some_and(|octet| octet & MULTICAST_BIT != 0)
+    }
+
+    /// Whether addresses of this block are locally administered, false if the prefix is shorter than an octet.
+    pub fn is_locally_administered(&self) -> bool {
+        self.first_octet().is_some_and(|octet| octet & LOCAL_BIT != 0)
+    }
+
+    /// Number of addresses in the block.
+    pub fn block_size(&self) -> u64 {
+        1u64 << (48 - self.len)
+    }
+
+    /// The lowest address of the block, the prefix followed by zeros.
+    pub fn network(&self) -> MacAddress {
+        MacAddress::from_bits(self.bits << (48 - self.len))
+    }
+
+}
+
+impl From<MacAddress> for OuiPrefix {
+
+    fn from(address: MacAddress) -> Self {
+        Self { bits: address.to_bits(), len: 48 }
+    }
+
+}
+
+impl FromStr for OuiPrefix {
+
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (address, mask) = match value.split_once('/') {
+            Some((address, mask)) => match mask.trim().parse::<u8>() {
+                Ok(mask) => (address, Some(mask)),
+                Err(_) => return Err(Error::InvalidMac(format!("Invalid prefix length in {}", value)))
+            },
+            None => (value, None)
+        };
+
+        let digits = hex_digits(address);
+        if digits.is_empty() || digits.len() > 12 {
+            return Err(Error::InvalidMac(String::from("Invalid prefix length")));
+        }
+        if !digits.chars().all(|character| character.is_ascii_hexdigit()) {
+            return Err(Error::InvalidMac(String::from("Invalid prefix character")));
+        }
+        let prefix = match u64::from_str_radix(&digits, 16) {
+            Ok(bits) => OuiPrefix::new(bits, (digits.len() * 4) as u8)?,
+            Err(_) => return Err(Error::InvalidMac(String::from("Invalid prefix character")))
+        };
+
+        return match mask {
+            Some(mask) if mask == 0 || mask > prefix.len => Err(Error::InvalidMac(format!("Invalid prefix length in {}", value))),
+            Some(mask) => Ok(prefix.truncate(mask)),
+            None => Ok(prefix)
+        };
+    }
+
+}
+
+impl fmt::Display for OuiPrefix {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.len.is_multiple_of(4) {
+            return write!(f, "{}/{}", self.network(), self.len);
+        }
+        let digits = format!("{:0width$X}", self.bits, width = (self.len / 4) as usize);
+        let groups = digits.as_bytes()
+            .chunks(2)
+            .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+            .collect::<Vec<_>>();
+        write!(f, "{}", groups.join(":"))
+    }
+
+}
+
+impl fmt::Display for AddressIssue {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            AddressIssue::Zero => write!(f, "the all-zero address"),
+            AddressIssue::Broadcast => write!(f, "the broadcast address"),
+            AddressIssue::Multicast => write!(f, "a multicast address"),
+            AddressIssue::LocallyAdministered => write!(f, "marked locally administered although it should carry a vendor OUI")
+        };
+    }
+
+}
+
+impl AddressIssue {
+
+    /// Whether the kernel refuses the address, the others are only suspicious.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, AddressIssue::LocallyAdministered)
+    }
+
+}
+
+impl Serialize for MacAddress {
+
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+
+}
+
+impl<'de> Deserialize<'de> for MacAddress {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+
+}
+
+impl Serialize for OuiPrefix {
+
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+
+}
+
+impl<'de> Deserialize<'de> for OuiPrefix {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+
+}
+
+/// Drops the separators of every supported notation.
+fn hex_digits(value: &str) -> String {
+    value.trim().replace([':', '-', '.'], "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressIssue, MacAddress, OuiPrefix};
+
+    const INTEL: MacAddress = MacAddress([0x00, 0x1B, 0x21, 0x3A, 0x4F, 0x5C]);
+
+    #[test]
+    fn address_notations_parse_alike() {
+        for notation in ["00:1B:21:3A:4F:5C", "00-1b-21-3a-4f-5c", "001b.213a.4f5c", "001B213A4F5C", " 00:1b:21:3a:4f:5c "] {
+            assert_eq!(notation.parse::<MacAddress>().unwrap(), INTEL, "{}", notation);
+        }
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        for notation in ["", "00:1B:21:3A:4F", "00:1B:21:3A:4F:5C:00", "00:1B:21:3A:4F:5G", "00:1B:21:3A:4F:+5"] {
+            assert!(notation.parse::<MacAddress>().is_err(), "{}", notation);
+        }
+    }
+
+    #[test]
+    fn address_display_round_trips() {
+        assert_eq!(INTEL.to_string(), "00:1B:21:3A:4F:5C");
+        assert_eq!(INTEL.to_string().parse::<MacAddress>().unwrap(), INTEL);
+        assert_eq!(MacAddress::from_bits(INTEL.to_bits()), INTEL);
+    }
+
+    #[test]
+    fn prefix_notations_parse_alike() {
+        let oui = OuiPrefix::new(0x001B21, 24).unwrap();
+        for notation in ["00:1B:21", "00-1b-21", "001b.21", "001B21", "00:1B:21:3A:4F:5C/24"] {
+            assert_eq!(notation.parse::<OuiPrefix>().unwrap(), oui, "{}", notation);
+        }
+    }
+
+    #[test]
+    fn prefix_length_comes_from_the_mask() {
+        let ma_s = "00:1B:C5:00:00:00/36".parse::<OuiPrefix>().unwrap();
+        assert_eq!(ma_s, OuiPrefix::new(0x001BC5000, 36).unwrap());
+        assert_eq!(ma_s.block_size(), 1 << 12);
+        assert_eq!(ma_s.to_string(), "00:1B:C5:00:0");
+
+        let unaligned = "00:1B:C5:80:00:00/25".parse::<OuiPrefix>().unwrap();
+        assert_eq!(unaligned.length(), 25);
+        assert_eq!(unaligned.to_string(), "00:1B:C5:80:00:00/25");
+    }
+
+    #[test]
+    fn prefix_display_round_trips() {
+        for notation in ["00:1B:21", "00:1B:C5:00:0", "70:B3:D5:0", "00:1B:C5:80:00:00/25", "00:1B:21:3A:4F:5C"] {
+            let prefix = notation.parse::<OuiPrefix>().unwrap();
+            assert_eq!(prefix.to_string(), notation);
+            assert_eq!(prefix.to_string().parse::<OuiPrefix>().unwrap(), prefix);
+        }
+    }
+
+    #[test]
+    fn invalid_prefixes_are_rejected() {
+        for notation in ["", "/24", "00:1B:21/0", "00:1B:21/28", "00:1B:21/x", "00:1B:2G", "00:1B:21:3A:4F:5C:00"] {
+            assert!(notation.parse::<OuiPrefix>().is_err(), "{}", notation);
+        }
+        assert!(OuiPrefix::new(0x1FF, 8).is_err());
+        assert!(OuiPrefix::new(0, 49).is_err());
+    }
+
+    #[test]
+    fn serde_uses_the_display_notation() {
+        let json = serde_json::to_string(&INTEL).unwrap();
+        assert_eq!(json, "\"00:1B:21:3A:4F:5C\"");
+        assert_eq!(serde_json::from_str::<MacAddress>("\"001b.213a.4f5c\"").unwrap(), INTEL);
+
+        let prefix = "00:1B:C5:00:00:00/36".parse::<OuiPrefix>().unwrap();
+        let json = serde_json::to_string(&prefix).unwrap();
+        assert_eq!(json, "\"00:1B:C5:00:0\"");
+        assert_eq!(serde_json::from_str::<OuiPrefix>(&json).unwrap(), prefix);
+        assert!(serde_json::from_str::<OuiPrefix>("\"not a prefix\"").is_err());
+    }
+
+    #[test]
+    fn issues_depend_on_the_vendor() {
+        assert_eq!(MacAddress([0; 6]).issues(false), vec![AddressIssue::Zero]);
+        assert_eq!(MacAddress([0xFF; 6]).issues(false)[0], AddressIssue::Broadcast);
+        assert_eq!("01:00:5E:00:00:01".parse::<MacAddress>().unwrap().issues(false), vec![AddressIssue::Multicast]);
+
+        let local = INTEL.into_local_unicast();
+        assert!(local.issues(false).is_empty());
+        assert_eq!(local.issues(true), vec![AddressIssue::LocallyAdministered]);
+        assert!(!AddressIssue::LocallyAdministered.is_fatal());
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::error::{Error, Result};
+use crate::storage;
+
+/// The `ETag` and `Last-Modified` headers of the last successful download of a datasource.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Validators {
+
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+
+}
+
+impl Validators {
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+}
+
+/// Validators of every HTTP datasource, stored next to the database and keyed by URL.
+#[derive(Serialize, Deserialize, Default)]
+pub struct HttpCache {
+
+    #[serde(skip)]
+    path: String,
+    /// Fingerprint of the `datasource.json` the database was built from, see `DataSources::fingerprint`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    datasource: Option<String>,
+    sources: BTreeMap<String, Validators>,
+
+}
+
+impl HttpCache {
+
+    pub fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Self { path: path.to_string(), ..Default::default() });
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(Error::io(format!("Failed to read {:?}", path), err))
+        };
+
+        let mut cache: HttpCache = match serde_json::from_str(content.as_str()) {
+            Ok(json) => json,
+            Err(err) => return Err(Error::json(format!("{:?}", path), &err))
+        };
+        cache.path = path.to_string();
+        return Ok(cache);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let serialize = match serde_json::to_string_pretty(&self) {
+            Ok(json) => json,
+            Err(_) => return Err(Error::Other(String::from("Failed to serialize JSON")))
+        };
+
+        return match storage::write_atomic(&self.path, serialize.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::io(format!("Failed to write {:?}", self.path), err))
+        };
+    }
+
+    /// Whether the database was built from the datasources with `fingerprint`, caches of older versions never are.
+    pub fn is_built_from(&self, fingerprint: &str) -> bool {
+        self.datasource.as_deref() == Some(fingerprint)
+    }
+
+    /// Records the datasources the database is rebuilt from, validators of sources that were removed are dropped.
+    pub fn set_datasource(&mut self, fingerprint: String, urls: &[&str]) {
+        self.sources.retain(|url, _| urls.contains(&url.as_str()));
+        self.datasource = Some(fingerprint);
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Validators> {
+        self.sources.get(url)
+    }
+
+    pub fn set(&mut self, url: &str, validators: Validators) {
+        if validators.is_empty() {
+            self.sources.remove(url);
+        } else {
+            self.sources.insert(url.to_string(), validators);
+        }
+    }
+
+}
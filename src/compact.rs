@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Deref;
+use memmap2::Mmap;
+use crate::address::OuiPrefix;
+use crate::error::{Error, Result};
+use crate::macaddress::MacInformation;
+use crate::record::MacRecord;
+
+const MAGIC: &[u8; 8] = b"RMACDB\0\0";
+pub const VERSION: u32 = 1;
+const HEADER_LEN: usize = 24;
+const ENTRY_LEN: usize = 28;
+const NO_STRING: u32 = u32::MAX;
+const FLAG_PRIVATE: u8 = 1;
+
+enum Bytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Bytes {
+
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Mapped(map) => map,
+            Bytes::Owned(bytes) => bytes
+        }
+    }
+
+}
+
+/// The binary database written by `update`.
+///
+/// ```text
+/// header   magic "RMACDB\0\0", version, entry count, string count, reserved   (24 bytes)
+/// entries  prefix bits u64, bit length u8, flags u8, reserved u16,
+///          vendor, block type, source and address string ids u32            (28 bytes each)
+/// strings  string count + 1 offsets u32 followed by the UTF-8 data
+/// ```
+///
+/// Entries are sorted by bit length, longest first, then by prefix so a lookup is one binary search
+/// per distinct length. Every string is stored once. All integers are little endian.
+pub struct CompactDatabase {
+    bytes: Bytes,
+    entries: usize,
+    strings: usize,
+    segments: Vec<(u8, usize, usize)>,
+}
+
+impl CompactDatabase {
+
+    /// Serializes `information` into the binary format.
+    pub fn build(information: &[Box<dyn MacInformation>]) -> Vec<u8> {
+        let mut sorted: Vec<(u64, u8, &Box<dyn MacInformation>)> = information.iter()
+            .map(|info| {
+                let prefix = info.prefix();
+                (prefix.bits(), prefix.length(), info)
+            })
+            .collect();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        sorted.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+
+        // Interned in entry order so the same entries always produce the same file
+        let mut strings: Vec<String> = Vec::new();
+        let mut interned: HashMap<String, u32> = HashMap::new();
+        let mut intern = |value: String| -> u32 {
+            if let Some(id) = interned.get(&value) {
+                return *id;
+            }
+            let id = strings.len() as u32;
+            interned.insert(value.clone(), id);
+            strings.push(value);
+            id
+        };
+
+        let mut entries: Vec<(u64, u8, u8, u32, u32, u32, u32)> = Vec::with_capacity(sorted.len());
+        for (bits, length, info) in sorted {
+            let flags = if info.is_private() { FLAG_PRIVATE } else { 0 };
+            let vendor = intern(info.vendor());
+            let block_type = intern(info.block_type());
+            let source = info.source().map_or(NO_STRING, &mut intern);
+            let address = info.address().map_or(NO_STRING, &mut intern);
+            entries.push((bits, length, flags, vendor, block_type, source, address));
+        }
+
+        let data_len: usize = strings.iter().map(|value| value.len()).sum();
+        let mut bytes = Vec::with_capacity(HEADER_LEN + entries.len() * ENTRY_LEN + (strings.len() + 1) * 4 + data_len);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        for (bits, length, flags, vendor, block_type, source, address) in entries {
+            bytes.extend_from_slice(&bits.to_le_bytes());
+            bytes.push(length);
+            bytes.push(flags);
+            bytes.extend_from_slice(&0u16.to_le_bytes());
+            bytes.extend_from_slice(&vendor.to_le_bytes());
+            bytes.extend_from_slice(&block_type.to_le_bytes());
+            bytes.extend_from_slice(&source.to_le_bytes());
+            bytes.extend_from_slice(&address.to_le_bytes());
+        }
+
+        let mut offset = 0u32;
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        for value in &strings {
+            offset += value.len() as u32;
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        for value in &strings {
+            bytes.extend_from_slice(value.as_bytes());
+        }
+        return bytes;
+    }
+
+    /// Memory maps the database at `path`.
+    pub fn open(path: &str) -> Result<Self> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => return Err(Error::io(format!("Failed to read {:?}", path), err))
+        };
+        // The file is only ever replaced by rename, so the mapping never changes under us
+        let map = match unsafe { Mmap::map(&file) } {
+            Ok(map) => map,
+            Err(err) => return Err(Error::io(format!("Failed to map {:?}", path), err))
+        };
+        return Self::parse(Bytes::Mapped(map));
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        return Self::parse(Bytes::Owned(bytes));
+    }
+
+    /// Whether the file at `path` starts with the binary database magic.
+    pub fn is_compact(path: &str) -> bool {
+        let mut magic = [0u8; 8];
+        return match File::open(path) {
+            Ok(mut file) => file.read_exact(&mut magic).is_ok() && &magic == MAGIC,
+            Err(_) => false
+        };
+    }
+
+    fn parse(bytes: Bytes) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[0..8] != MAGIC {
+            return Err(Error::parse("database", "not a binary database"));
+        }
+        let version = read_u32(&bytes, 8);
+        if version != VERSION {
+            return Err(Error::parse("database", format!("unsupported version {}, run `update` to rebuild it", version)));
+        }
+        let entries = read_u32(&bytes, 12) as usize;
+        let strings = read_u32(&bytes, 16) as usize;
+
+        let strings_offset = HEADER_LEN + entries * ENTRY_LEN;
+        let data_offset = strings_offset + (strings + 1) * 4;
+        if bytes.len() < data_offset || bytes.len() < data_offset + read_u32(&bytes, data_offset - 4) as usize {
+            return Err(Error::parse("database", "truncated"));
+        }
+
+        let mut database = Self { bytes, entries, strings, segments: Vec::new() };
+        // Lookups depend on the order and `prefix` on valid lengths, so every entry is checked once here
+        for position in 0..entries {
+            let (length, bits) = (database.length(position), database.bits(position));
+            if length == 0 || length > 48 || bits >> length != 0 {
+                return Err(Error::parse("database", format!("invalid prefix {:X}/{} of entry {}", bits, length, position)));
+            }
+            if let Some(previous) = position.checked_sub(1) {
+                let previous_length = database.length(previous);
+                if previous_length < length || (previous_length == length && database.bits(previous) >= bits) {
+                    return Err(Error::parse("database", format!("entry {} is out of order", position)));
+                }
+            }
+            match database.segments.last_mut() {
+                Some((segment_length, _, end)) if *segment_length == length => *end = position + 1,
+                _ => database.segments.push((length, position, position + 1))
+            }
+        }
+        return Ok(database);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    /// Position of the most specific block containing `prefix`, which can also be a whole address.
+    pub fn lookup(&self, prefix: &OuiPrefix) -> Option<usize> {
+        for (block_length, start, end) in &self.segments {
+            if *block_length > prefix.length() {
+                continue;
+            }
+            let key = prefix.truncate(*block_length).bits();
+            let position = start + self.partition(*start, *end, |position| self.bits(position) < key);
+            if position < *end && self.bits(position) == key {
+                return Some(position);
+            }
+        }
+        return None;
+    }
+
+    pub fn record(&self, position: usize) -> MacRecord {
+        let entry = self.entry(position);
+        return MacRecord {
+            prefix: self.prefix(position),
+            vendor: self.string(read_u32(&self.bytes, entry + 12)).to_string(),
+            block_type: self.string(read_u32(&self.bytes, entry + 16)).to_string(),
+            private: self.bytes[entry + 9] & FLAG_PRIVATE != 0,
+            source: self.optional_string(read_u32(&self.bytes, entry + 20)),
+            address: self.optional_string(read_u32(&self.bytes, entry + 24))
+        };
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = MacRecord> + '_ {
+        (0..self.entries).map(|position| self.record(position))
+    }
+
+    /// Vendor name of the entry at `position`, without building the whole record.
+    pub fn vendor(&self, position: usize) -> &str {
+        self.string(read_u32(&self.bytes, self.entry(position) + 12))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn prefix(&self, position: usize) -> OuiPrefix {
+        let length = self.length(position);
+        // `parse` checked the length and bits of every entry
+        OuiPrefix::new(self.bits(position), length)
+            .expect("Invalid prefix")
+    }
+
+    fn bits(&self, position: usize) -> u64 {
+        let entry = self.entry(position);
+        let mut bits = [0u8; 8];
+        bits.copy_from_slice(&self.bytes[entry..entry + 8]);
+        u64::from_le_bytes(bits)
+    }
+
+    fn length(&self, position: usize) -> u8 {
+        self.bytes[self.entry(position) + 8]
+    }
+
+    /// Offset of the entry at `position`.
+    fn entry(&self, position: usize) -> usize {
+        HEADER_LEN + position * ENTRY_LEN
+    }
+
+    fn string(&self, id: u32) -> &str {
+        let id = id as usize;
+        if id >= self.strings {
+            return "";
+        }
+        let strings_offset = HEADER_LEN + self.entries * ENTRY_LEN;
+        let data_offset = strings_offset + (self.strings + 1) * 4;
+        let start = data_offset + read_u32(&self.bytes, strings_offset + id * 4) as usize;
+        let end = data_offset + read_u32(&self.bytes, strings_offset + (id + 1) * 4) as usize;
+        if start > end || end > self.bytes.len() {
+            return "";
+        }
+        std::str::from_utf8(&self.bytes[start..end]).unwrap_or("")
+    }
+
+    fn optional_string(&self, id: u32) -> Option<String> {
+        if id == NO_STRING { None } else { Some(self.string(id).to_string()) }
+    }
+
+    /// Number of positions in `start..end` for which `predicate` holds, the predicate has to be
+    /// true for a prefix of the range.
+    fn partition(&self, start: usize, end: usize, predicate: impl Fn(usize) -> bool) -> usize {
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if predicate(middle) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        low - start
+    }
+
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::address::OuiPrefix;
+    use crate::macaddress::MacInformation;
+    use crate::record::MacRecord;
+    use super::{CompactDatabase, ENTRY_LEN, HEADER_LEN};
+
+    fn record(prefix: &str, vendor: &str, block_type: &str, address: Option<&str>) -> Box<dyn MacInformation> {
+        Box::new(MacRecord {
+            prefix: prefix.parse().unwrap(),
+            vendor: vendor.to_string(),
+            block_type: block_type.to_string(),
+            private: false,
+            source: Some(String::from("ieee")),
+            address: address.map(|address| address.to_string())
+        })
+    }
+
+    fn registry() -> Vec<Box<dyn MacInformation>> {
+        vec![
+            record("8C:1F:64", "IEEE Registration Authority", "MA-L", Some("445 Hoes Lane Piscataway NJ US 08554")),
+            record("8C:1F:64:F", "Medium Co", "MA-M", None),
+            record("8C:1F:64:F2:1", "Specific Co", "MA-S", Some("Somewhere")),
+            record("00:1B:21", "Intel Corporate", "MA-L", None)
+        ]
+    }
+
+    fn vendor_of(database: &CompactDatabase, address: &str) -> Option<String> {
+        let prefix = address.parse::<OuiPrefix>().unwrap();
+        database.lookup(&prefix).map(|position| database.vendor(position).to_string())
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let database = CompactDatabase::from_bytes(CompactDatabase::build(&registry())).unwrap();
+        assert_eq!(database.len(), 4);
+        assert_eq!(vendor_of(&database, "8C:1F:64:F2:1A:BC").as_deref(), Some("Specific Co"));
+        assert_eq!(vendor_of(&database, "8C:1F:64:F3:00:00").as_deref(), Some("Medium Co"));
+        assert_eq!(vendor_of(&database, "8C:1F:64:01:00:00").as_deref(), Some("IEEE Registration Authority"));
+        assert_eq!(vendor_of(&database, "00:1B:21:12:34:56").as_deref(), Some("Intel Corporate"));
+        // A prefix only matches blocks that aren't longer than itself
+        assert_eq!(vendor_of(&database, "8C:1F:64:F").as_deref(), Some("Medium Co"));
+        assert_eq!(vendor_of(&database, "8C:1F").as_deref(), None);
+        assert_eq!(vendor_of(&database, "00:00:00:00:00:01").as_deref(), None);
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let database = CompactDatabase::from_bytes(CompactDatabase::build(&registry())).unwrap();
+        let position = database.lookup(&"8C:1F:64:F2:1A:BC".parse().unwrap()).unwrap();
+        let record = database.record(position);
+        assert_eq!(record.prefix().to_string(), "8C:1F:64:F2:1");
+        assert_eq!(record.block_type(), "MA-S");
+        assert_eq!(record.source().as_deref(), Some("ieee"));
+        assert_eq!(record.address().as_deref(), Some("Somewhere"));
+
+        let mut vendors = database.records().map(|record| record.vendor()).collect::<Vec<_>>();
+        vendors.sort();
+        assert_eq!(vendors, vec!["IEEE Registration Authority", "Intel Corporate", "Medium Co", "Specific Co"]);
+        assert_eq!(CompactDatabase::build(&registry()), database.bytes());
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let bytes = CompactDatabase::build(&registry());
+        assert!(CompactDatabase::from_bytes(bytes[..bytes.len() - 1].to_vec()).is_err());
+        assert!(CompactDatabase::from_bytes(b"[{\"prefix\": \"00:1B:21\"}]".to_vec()).is_err());
+
+        let mut future = bytes.clone();
+        future[8..12].copy_from_slice(&99u32.to_le_bytes());
+        assert!(CompactDatabase::from_bytes(future).is_err());
+
+        // Every entry is checked, not only the first of each prefix length
+        for position in 0..4 {
+            for length in [0u8, 60, 200] {
+                let mut damaged = bytes.clone();
+                damaged[HEADER_LEN + position * ENTRY_LEN + 8] = length;
+                assert!(CompactDatabase::from_bytes(damaged).is_err(), "entry {} with length {}", position, length);
+            }
+        }
+
+        // Longer prefixes come first
+        let mut longer = bytes.clone();
+        longer[HEADER_LEN + ENTRY_LEN + 8] = 40;
+        assert!(CompactDatabase::from_bytes(longer).is_err());
+
+        // Entries 2 and 3 are both MA-L, a duplicate or swapped prefix breaks the binary search
+        let mut duplicate = bytes.clone();
+        let (second, third) = (HEADER_LEN + 2 * ENTRY_LEN, HEADER_LEN + 3 * ENTRY_LEN);
+        duplicate.copy_within(second..second + 8, third);
+        assert!(CompactDatabase::from_bytes(duplicate).is_err());
+
+        let mut stray_bits = bytes.clone();
+        stray_bits[third + 7] = 0xFF;
+        assert!(CompactDatabase::from_bytes(stray_bits).is_err());
+    }
+}
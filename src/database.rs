@@ -0,0 +1,217 @@
+//! The local copy of the registries, see `AddressDatabase`.
+
+use std::fs;
+use std::path::Path;
+use crate::address::OuiPrefix;
+use crate::cache::HttpCache;
+use crate::compact::CompactDatabase;
+use crate::error::{Error, Result};
+use crate::macaddress::{self, DataSources, MacInformation};
+use crate::record::{self, MacRecord};
+use crate::storage::{self, lock};
+use crate::vendor::{self, BlockFilter, VendorAliases, VendorMatch, VendorQuery};
+
+/// The registry blocks of every configured datasource, stored at `path` in the compact binary format.
+pub struct AddressDatabase {
+    path: String,
+    database: CompactDatabase
+}
+
+impl AddressDatabase {
+
+    /// Builds a database from registry entries, nothing is written until `save`.
+    pub fn new(path: String, information: Vec<Box<dyn MacInformation>>) -> Result<Self> {
+        let database = CompactDatabase::from_bytes(CompactDatabase::build(&information))?;
+        return Ok(Self {
+            path,
+            database
+        });
+    }
+
+    /// Maps the binary database at `path`.
+    pub fn open(path: String) -> Result<Self> {
+        let database = CompactDatabase::open(&path)?;
+        return Ok(Self {
+            path,
+            database
+        });
+    }
+
+    /// Maps a binary database, anything else is read as a JSON export or a database of an older version.
+    pub fn read(path: String) -> Result<Self> {
+        if CompactDatabase::is_compact(&path) {
+            return Self::open(path);
+        }
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => return Err(Error::io(format!("Failed to read {:?}", path), err))
+        };
+
+        let records = record::from_json(&content)?;
+        return Self::new(path, boxed(records));
+    }
+
+    /// Reads the database at `path`, converting a legacy JSON database or downloading `datasource` if it doesn't
+    /// exist yet. A registry installed by the distribution is used instead of the default datasource, or of one that
+    /// can't be downloaded.
+    ///
+    /// Readers share the lock of `path`, so this waits while another process updates the database.
+    pub fn load(datasource: DataSources, path: String) -> Result<Self> {
+        {
+            let _lock = lock(&path, false)?;
+            if Path::new(&path).exists() {
+                return Self::read(path);
+            }
+        }
+
+        // Another process may have downloaded the database while we waited for the lock
+        let _lock = lock(&path, true)?;
+        return if Path::new(&path).exists() {
+            Self::read(path)
+        } else if let Some(legacy) = legacy_database(&path).filter(|legacy| Path::new(legacy).exists()) {
+            let mut database = Self::read(legacy)?;
+            database.path = path;
+            database.save()?;
+            Ok(database)
+        } else {
+            let mut cache = HttpCache::load(&http_cache(&path))?;
+            let information = datasource.fetch_initial(&mut cache)?;
+            let database = Self::new(path, information)?;
+            database.save()?;
+            cache.save()?;
+            Ok(database)
+        };
+    }
+
+    /// Downloads `datasource` again and replaces the database at `path`.
+    ///
+    /// Returns `None` if the database exists and every source answered that it didn't change. Without a database the
+    /// datasource falls back to a registry installed by the distribution like in `load`.
+    pub fn update(datasource: DataSources, path: String) -> Result<Option<Self>> {
+        let _lock = lock(&path, true)?;
+        let mut cache = HttpCache::load(&http_cache(&path))?;
+        let information = if Path::new(&path).exists() {
+            match datasource.fetch_information(&mut cache, true)? {
+                Some(information) => information,
+                None => return Ok(None)
+            }
+        } else {
+            datasource.fetch_initial(&mut cache)?
+        };
+
+        let database = Self::new(path, information)?;
+        database.save()?;
+        cache.save()?;
+        return Ok(Some(database));
+    }
+
+    /// Replaces the database at `path` with `content`, either a JSON export or a registry in the format of the
+    /// first source of `datasource`.
+    ///
+    /// Content that isn't a registry either keeps the error of the JSON parser.
+    pub fn import(datasource: &DataSources, path: String, content: String) -> Result<Self> {
+        let information = match record::from_json(&content) {
+            Ok(records) => boxed(records),
+            Err(error) => match macaddress::convert(datasource.sources[0].name(), content) {
+                Ok(information) if !information.is_empty() => information,
+                _ => return Err(error)
+            }
+        };
+
+        let _lock = lock(&path, true)?;
+        let database = Self::new(path, information)?;
+        database.save()?;
+        return Ok(database);
+    }
+
+    /// Where the database is stored.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Number of registry blocks.
+    pub fn len(&self) -> usize {
+        self.database.len()
+    }
+
+    /// Whether the database has no blocks at all.
+    pub fn is_empty(&self) -> bool {
+        self.database.len() == 0
+    }
+
+    /// Every block, longest prefixes first.
+    pub fn records(&self) -> impl Iterator<Item = MacRecord> + '_ {
+        self.database.records()
+    }
+
+    /// Finds the most specific block containing `prefix`, which can also be a whole address.
+    pub fn lookup(&self, prefix: &OuiPrefix) -> Option<MacRecord> {
+        return self.database.lookup(prefix)
+            .map(|position| self.database.record(position));
+    }
+
+    /// The block at `position`, as found in a `VendorMatch`.
+    pub fn record(&self, position: usize) -> MacRecord {
+        self.database.record(position)
+    }
+
+    /// Every company matching `query` with the positions of its blocks, best match first.
+    pub fn vendors(&self, query: &VendorQuery, aliases: &VendorAliases) -> Vec<VendorMatch> {
+        let vendors = (0..self.database.len()).map(|position| (position, self.database.vendor(position)));
+        return vendor::search(vendors, query, aliases);
+    }
+
+    /// The blocks of `vendor` that `filter` accepts.
+    pub fn blocks(&self, vendor: &VendorMatch, filter: &BlockFilter) -> Vec<MacRecord> {
+        return vendor.positions.iter()
+            .map(|position| self.record(*position))
+            .filter(|record| filter.accepts(record))
+            .collect();
+    }
+
+    /// Writes the database to its `path`, replacing the file atomically.
+    pub fn save(&self) -> Result<()> {
+        return match storage::write_atomic(&self.path, self.database.bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::io(format!("Failed to write {:?}", self.path), err))
+        };
+    }
+
+    /// The records as JSON in the current schema, see `record::SCHEMA_VERSION`.
+    pub fn export(&self) -> Result<String> {
+        return record::to_json(&self.records().collect::<Vec<_>>());
+    }
+
+    /// Writes the `export` to `path`, replacing the file atomically.
+    pub fn export_to(&self, path: &str) -> Result<()> {
+        return match storage::write_atomic(path, self.export()?.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::io(format!("Failed to write {:?}", path), err))
+        };
+    }
+
+}
+
+/// The JSON database written next to `database` before the binary format, imported once when
+/// `database` doesn't exist yet.
+#[inline]
+pub fn legacy_database(database: &str) -> Option<String> {
+    let path = Path::new(database);
+    if path.extension()? != "bin" {
+        return None;
+    }
+    return Some(path.with_extension("json").to_string_lossy().to_string());
+}
+
+/// HTTP validators of the datasources, kept next to the database they produced.
+#[inline]
+fn http_cache(database: &str) -> String {
+    return format!("{}.http.json", database);
+}
+
+fn boxed(records: Vec<MacRecord>) -> Vec<Box<dyn MacInformation>> {
+    return records.into_iter()
+        .map(|record| Box::new(record) as Box<dyn MacInformation>)
+        .collect();
+}
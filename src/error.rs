@@ -0,0 +1,128 @@
+//! The error type of every fallible function of the crate.
+
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong in `random_mac`, every kind has its own exit code in the CLI.
+#[derive(Debug)]
+pub enum Error {
+
+    /// Reading or writing a file, stdin or a socket failed, `context` says which.
+    Io {
+        /// What was being done, like `Failed to read "database.bin"`.
+        context: String,
+        /// The error of the operating system.
+        source: io::Error
+    },
+    /// A datasource couldn't be downloaded or the server answered with an error status.
+    Http {
+        /// The URL of the datasource or its signature.
+        url: String,
+        /// What went wrong.
+        message: String
+    },
+    /// `what` isn't in the expected format, `line` and `column` are 1-based and point at the problem when known.
+    Parse {
+        /// The file, URL or kind of data, like `JSON`.
+        what: String,
+        /// Line of the problem.
+        line: Option<usize>,
+        /// Column of the problem, only known for JSON.
+        column: Option<usize>,
+        /// What is wrong.
+        message: String
+    },
+    /// An address or prefix that can't be parsed or that interfaces don't accept.
+    InvalidMac(String),
+    /// Changing interfaces and writing the state needs root or `CAP_NET_ADMIN`.
+    Permission(String),
+    /// There is no network interface by that name.
+    InterfaceNotFound(String),
+    /// Anything else, like an ambiguous vendor name or a datasource failing its signature check.
+    Other(String),
+
+}
+
+/// `std::result::Result` with the `Error` of this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+
+    /// Wraps an IO error, a denied access becomes a `Permission` error.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        let context = context.into();
+        if source.kind() == io::ErrorKind::PermissionDenied {
+            return Error::Permission(format!("{}: {}", context, source));
+        }
+        return Error::Io { context, source };
+    }
+
+    /// A parse error without a known position.
+    pub fn parse(what: impl Into<String>, message: impl Into<String>) -> Self {
+        return Error::Parse { what: what.into(), line: None, column: None, message: message.into() };
+    }
+
+    /// A parse error at `line`, and `column` if it is known.
+    pub fn parse_at(what: impl Into<String>, line: usize, column: Option<usize>, message: impl Into<String>) -> Self {
+        return Error::Parse { what: what.into(), line: Some(line), column, message: message.into() };
+    }
+
+    /// Keeps the position serde_json reports, values that didn't come from text have none.
+    ///
+    /// serde_json reports column 0 at the start of a line, e.g. for a truncated file, that column is left out.
+    pub fn json(what: impl Into<String>, error: &serde_json::Error) -> Self {
+        let message = error.to_string();
+        if error.line() == 0 {
+            return Error::parse(what, message);
+        }
+        let position = format!(" at line {} column {}", error.line(), error.column());
+        let message = message.strip_suffix(position.as_str()).unwrap_or(&message);
+        let column = Some(error.column()).filter(|column| *column > 0);
+        return Error::parse_at(what, error.line(), column, message);
+    }
+
+    /// Exit code of the CLI for this kind of error, clap already exits with 2 on invalid arguments.
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            Error::Other(_) => 1,
+            Error::Io { .. } => 3,
+            Error::Http { .. } => 4,
+            Error::Parse { .. } => 5,
+            Error::InvalidMac(_) => 6,
+            Error::Permission(_) => 7,
+            Error::InterfaceNotFound(_) => 8
+        };
+    }
+
+}
+
+impl fmt::Display for Error {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+            Error::Http { url, message } => write!(f, "{} ({})", message, url),
+            Error::Parse { what, line: Some(line), column: Some(column), message } =>
+                write!(f, "Failed to parse {} at line {}, column {}: {}", what, line, column, message),
+            Error::Parse { what, line: Some(line), column: None, message } =>
+                write!(f, "Failed to parse {} at line {}: {}", what, line, message),
+            Error::Parse { what, line: None, message, .. } => write!(f, "Failed to parse {}: {}", what, message),
+            Error::InvalidMac(message)
+            | Error::Permission(message)
+            | Error::InterfaceNotFound(message)
+            | Error::Other(message) => write!(f, "{}", message)
+        };
+    }
+
+}
+
+impl std::error::Error for Error {
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None
+        };
+    }
+
+}
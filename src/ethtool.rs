@@ -0,0 +1,68 @@
+use std::fs;
+use std::io;
+use std::mem;
+use crate::address::MacAddress;
+
+const ETHTOOL_GPERMADDR: u32 = 0x00000020;
+const MAX_ADDR_LEN: usize = 32;
+
+/// Value of `addr_assign_type` for an address that came from the hardware (`NET_ADDR_PERM`).
+pub const NET_ADDR_PERM: u8 = 0;
+
+/// `struct ethtool_perm_addr` followed by room for the address bytes.
+#[repr(C)]
+struct EthtoolPermAddr {
+    cmd: u32,
+    size: u32,
+    data: [u8; MAX_ADDR_LEN],
+}
+
+/// Reads the burned-in address of `interface` with the `ETHTOOL_GPERMADDR` ioctl.
+///
+/// Returns `None` when the driver doesn't report one, which is the case for most virtual devices.
+pub fn permanent_address(interface: &str) -> io::Result<Option<MacAddress>> {
+    if interface.len() >= libc::IFNAMSIZ {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid interface name"));
+    }
+
+    let mut request = EthtoolPermAddr {
+        cmd: ETHTOOL_GPERMADDR,
+        size: MAX_ADDR_LEN as u32,
+        data: [0; MAX_ADDR_LEN],
+    };
+
+    let mut ifreq: libc::ifreq = unsafe { mem::zeroed() };
+    for (target, source) in ifreq.ifr_name.iter_mut().zip(interface.bytes()) {
+        *target = source as libc::c_char;
+    }
+    ifreq.ifr_ifru.ifru_data = &mut request as *mut EthtoolPermAddr as *mut libc::c_char;
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let result = unsafe { libc::ioctl(fd, libc::SIOCETHTOOL as _, &mut ifreq) };
+    let error = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+
+    if result < 0 {
+        return match error.raw_os_error() {
+            Some(libc::EOPNOTSUPP) => Ok(None),
+            _ => Err(error)
+        };
+    }
+
+    if request.size != 6 || request.data[..6].iter().all(|octet| *octet == 0) {
+        return Ok(None);
+    }
+
+    let mut address = [0u8; 6];
+    address.copy_from_slice(&request.data[..6]);
+    return Ok(Some(MacAddress(address)));
+}
+
+/// Reads `/sys/class/net/<interface>/addr_assign_type`.
+pub fn addr_assign_type(interface: &str) -> Option<u8> {
+    let content = fs::read_to_string(format!("/sys/class/net/{}/addr_assign_type", interface)).ok()?;
+    content.trim().parse().ok()
+}
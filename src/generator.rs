@@ -0,0 +1,124 @@
+use rand::rngs::ThreadRng;
+use rand::Rng;
+
+/// Draws the host part of an address uniformly from the whole space left by the prefix.
+pub struct SuffixGenerator<R: Rng> {
+    rng: R,
+}
+
+impl SuffixGenerator<ThreadRng> {
+
+    pub fn new() -> Self {
+        Self::with_rng(rand::thread_rng())
+    }
+
+}
+
+impl<R: Rng> SuffixGenerator<R> {
+
+    pub fn with_rng(rng: R) -> Self {
+        Self { rng }
+    }
+
+    /// Returns a uniformly distributed value in `0..2^bits`, every octet including `FF` is reachable.
+    pub fn suffix(&mut self, bits: u8) -> u64 {
+        if bits == 0 {
+            return 0;
+        }
+        let max = if bits >= 64 { u64::MAX } else { (1u64 << bits) - 1 };
+        return self.rng.gen_range(0..=max);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use super::SuffixGenerator;
+
+    const SAMPLES_PER_BIN: usize = 1000;
+
+    fn generator() -> SuffixGenerator<StdRng> {
+        SuffixGenerator::with_rng(StdRng::seed_from_u64(0x5EED))
+    }
+
+    fn chi_square(counts: &[usize], expected: f64) -> f64 {
+        counts.iter()
+            .map(|count| (*count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    #[test]
+    fn suffix_stays_inside_the_block() {
+        let mut generator = generator();
+        for bits in [0u8, 4, 12, 20, 24] {
+            for _ in 0..10_000 {
+                assert!(generator.suffix(bits) < 1u64 << bits);
+            }
+        }
+    }
+
+    #[test]
+    fn every_octet_value_is_reachable() {
+        let mut generator = generator();
+        let mut seen = [false; 256];
+        for _ in 0..256 * SAMPLES_PER_BIN {
+            seen[(generator.suffix(24) & 0xFF) as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen), "octet values missing from output");
+    }
+
+    #[test]
+    fn octets_are_uniform() {
+        let mut generator = generator();
+        let samples = 256 * SAMPLES_PER_BIN;
+        let mut counts = [[0usize; 256]; 3];
+        for _ in 0..samples {
+            let suffix = generator.suffix(24);
+            for (octet, counts) in counts.iter_mut().enumerate() {
+                counts[((suffix >> (octet * 8)) & 0xFF) as usize] += 1;
+            }
+        }
+
+        // Critical value of the chi-square distribution, 255 degrees of freedom, p = 0.001
+        for counts in &counts {
+            let statistic = chi_square(counts, SAMPLES_PER_BIN as f64);
+            assert!(statistic < 330.52, "chi-square {} over the critical value", statistic);
+        }
+    }
+
+    #[test]
+    fn nibble_suffix_is_uniform() {
+        let mut generator = generator();
+        let mut counts = [0usize; 16];
+        for _ in 0..16 * SAMPLES_PER_BIN {
+            counts[generator.suffix(4) as usize] += 1;
+        }
+
+        // Critical value of the chi-square distribution, 15 degrees of freedom, p = 0.001
+        let statistic = chi_square(&counts, SAMPLES_PER_BIN as f64);
+        assert!(statistic < 37.70, "chi-square {} over the critical value", statistic);
+    }
+
+    #[test]
+    fn bits_are_balanced() {
+        let mut generator = generator();
+        let samples = 100_000;
+        let mut ones = [0usize; 24];
+        for _ in 0..samples {
+            let suffix = generator.suffix(24);
+            for (bit, ones) in ones.iter_mut().enumerate() {
+                *ones += ((suffix >> bit) & 1) as usize;
+            }
+        }
+
+        // Five standard deviations of a fair coin over the sample count
+        let tolerance = 5.0 * (samples as f64 * 0.25).sqrt();
+        for (bit, ones) in ones.iter().enumerate() {
+            let deviation = (*ones as f64 - samples as f64 / 2.0).abs();
+            assert!(deviation < tolerance, "bit {} set {} times out of {}", bit, ones, samples);
+        }
+    }
+
+}
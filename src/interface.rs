@@ -0,0 +1,221 @@
+//! Reading and changing the addresses of network interfaces.
+//!
+//! Commands changing several interfaces first `plan` an address for each of them and only change any once every
+//! address is known to be accepted, see `apply_plan`.
+
+use crate::address::{AddressIssue, MacAddress, OuiPrefix};
+use crate::database::AddressDatabase;
+use crate::error::{Error, Result};
+use crate::ethtool;
+use crate::macaddress::MacInformation;
+use crate::netlink;
+use crate::state::InterfaceState;
+use crate::storage::lock;
+
+/// A new address for an interface, generated and checked before any interface is changed.
+pub struct PlannedAddress {
+
+    /// Name of the interface.
+    pub interface: String,
+    /// The new address, or why none could be generated or interfaces wouldn't accept it.
+    pub address: Result<MacAddress>,
+    /// Issues of the address that don't keep interfaces from accepting it.
+    pub warnings: Vec<AddressIssue>,
+
+}
+
+/// The address `interface` currently uses, `None` if there is no such interface.
+pub fn current_address(interface: &str) -> Result<Option<MacAddress>> {
+    return match mac_address::mac_address_by_name(interface) {
+        Ok(mac) => Ok(mac.map(|mac| MacAddress(mac.bytes()))),
+        Err(err) => Err(Error::Other(format!("Failed to get MAC address of {}: {}", interface, err)))
+    };
+}
+
+/// The burned-in address the driver reports through ethtool, if it reports one.
+pub fn permanent_address(interface: &str) -> Result<Option<MacAddress>> {
+    return match ethtool::permanent_address(interface) {
+        Ok(address) => Ok(address),
+        Err(err) if err.raw_os_error() == Some(libc::ENODEV) => Err(not_found(interface)),
+        Err(err) => Err(Error::io(format!("Failed to read permanent MAC address of {}", interface), err))
+    };
+}
+
+/// Best guess of the address `interface` shipped with: the ethtool permanent address, the
+/// current address if the kernel says it is the hardware one, the original recorded in `state`, or
+/// the current address as a last resort.
+pub fn hardware_address(interface: &str, current: MacAddress, state: &str) -> MacAddress {
+    if let Ok(Some(address)) = ethtool::permanent_address(interface) {
+        return address;
+    }
+
+    if ethtool::addr_assign_type(interface) == Some(ethtool::NET_ADDR_PERM) {
+        return current;
+    }
+
+    if let Ok(interface_state) = InterfaceState::load(state) {
+        if let Some(original) = interface_state.original(interface) {
+            return original;
+        }
+    }
+
+    return current;
+}
+
+/// The address `restore` puts back: the recorded original unless `permanent`, else the burned-in one.
+pub(crate) fn original_address(state: &InterfaceState, interface: &str, permanent: bool) -> Result<MacAddress> {
+    if !permanent {
+        if let Some(original) = state.original(interface) {
+            return Ok(original);
+        }
+    }
+    return match permanent_address(interface)? {
+        Some(address) => Ok(address),
+        None => Err(Error::Other(format!("No original MAC address known for interface {}!", interface)))
+    };
+}
+
+/// Stores `mac` as the original address of `interface` in the state file, unless one is recorded already.
+pub fn record_original(state: &str, interface: &str, mac: MacAddress) -> Result<()> {
+    let _lock = lock(state, true)?;
+    let mut interface_state = InterfaceState::load(state)?;
+    if interface_state.record(interface, mac) {
+        interface_state.save()?;
+    }
+    return Ok(());
+}
+
+/// Records the current address of `interface` as its original one in `state`, then changes it to `mac`.
+pub fn apply(mac: &MacAddress, interface: &str, state: &str) -> Result<()> {
+    let current = match current_address(interface)? {
+        Some(current) => current,
+        None => return Err(not_found(interface))
+    };
+    record_original(state, interface, current)?;
+    return set_address(interface, mac);
+}
+
+/// Checks `mac` before it is assigned and returns the issues interfaces don't mind.
+///
+/// `vendor` addresses are meant to carry an OUI and get a warning when they are marked locally administered.
+pub fn check(mac: &MacAddress, vendor: bool) -> Result<Vec<AddressIssue>> {
+    let issues = mac.issues(vendor);
+    if let Some(issue) = issues.iter().find(|issue| issue.is_fatal()) {
+        return Err(Error::InvalidMac(format!("{} is {}, interfaces only accept unicast addresses", mac, issue)));
+    }
+    return Ok(issues);
+}
+
+/// Generates an address for every interface with `generate` and checks it, see `check` for `vendor`.
+///
+/// Nothing is changed yet, `apply_plan` only changes the interfaces if every one of them got an address.
+pub fn plan(interfaces: &[String], vendor: bool, mut generate: impl FnMut(&str) -> Result<MacAddress>) -> Vec<PlannedAddress> {
+    return interfaces.iter()
+        .map(|interface| {
+            let checked = generate(interface).and_then(|mac| Ok((mac, check(&mac, vendor)?)));
+            let (address, warnings) = match checked {
+                Ok((mac, issues)) => (Ok(mac), issues),
+                Err(error) => (Err(error), Vec::new())
+            };
+            PlannedAddress { interface: interface.clone(), address, warnings }
+        })
+        .collect();
+}
+
+/// Changes every planned interface with `apply`, returning each with its new address or why it failed, in order.
+///
+/// Fails with the error of the first interface that has no address without changing any, so interfaces are
+/// either all attempted or left alone. A failing interface doesn't stop the others.
+pub fn apply_plan(plan: Vec<PlannedAddress>, state: &str) -> Result<Vec<(String, Result<MacAddress>)>> {
+    let mut addresses = Vec::with_capacity(plan.len());
+    for planned in plan {
+        addresses.push((planned.interface, planned.address?));
+    }
+    return Ok(addresses.into_iter()
+        .map(|(interface, address)| {
+            let result = apply(&address, &interface, state).map(|_| address);
+            (interface, result)
+        })
+        .collect());
+}
+
+/// Draws an address from the block of the vendor that made `interface`, matched by its burned-in address since
+/// the current one is random after the first change.
+pub fn random_vendor_address(database: &AddressDatabase, interface: &str, state: &str) -> Result<MacAddress> {
+    let current = match current_address(interface)? {
+        Some(current) => current,
+        None => return Err(not_found(interface))
+    };
+    let hardware = hardware_address(interface, current, state);
+    return match database.lookup(&OuiPrefix::from(hardware)) {
+        Some(block) => block.random_from_prefix(),
+        None => Err(Error::Other(format!("No registered vendor found for {}", hardware)))
+    };
+}
+
+/// Puts back the original address of every interface in `interfaces`, or of every recorded one if it is empty.
+///
+/// See `original_address` for `permanent`. Restored interfaces are forgotten in `state`, the others stay recorded.
+/// Returns each interface with the address it got back or why it failed, in order.
+pub fn restore(interfaces: &[String], permanent: bool, state: &str) -> Result<Vec<(String, Result<MacAddress>)>> {
+    let _lock = lock(state, true)?;
+    let mut interface_state = InterfaceState::load(state)?;
+
+    let interfaces = if interfaces.is_empty() {
+        interface_state.interfaces()
+    } else {
+        interfaces.to_vec()
+    };
+
+    let mut restored = Vec::with_capacity(interfaces.len());
+    for interface in interfaces {
+        let result = original_address(&interface_state, &interface, permanent)
+            .and_then(|original| set_address(&interface, &original).map(|_| original));
+        if result.is_ok() {
+            interface_state.forget(&interface);
+        }
+        restored.push((interface, result));
+    }
+
+    interface_state.save()?;
+    return Ok(restored);
+}
+
+/// Changes the address of `interface` over netlink, taking the link down for the change. Needs `CAP_NET_ADMIN`.
+pub fn set_address(interface: &str, mac: &MacAddress) -> Result<()> {
+    if let Some(issue) = mac.issues(false).first() {
+        return Err(Error::InvalidMac(format!("{} is {}", mac, issue)));
+    }
+
+    let index = match netlink::interface_index(interface) {
+        Ok(index) => index,
+        Err(err) if err.raw_os_error() == Some(libc::ENODEV) => return Err(not_found(interface)),
+        Err(err) => return Err(Error::io(format!("Failed to find interface {}", interface), err))
+    };
+
+    let mut socket = match netlink::NetlinkSocket::open() {
+        Ok(socket) => socket,
+        Err(err) => return Err(Error::io("Failed to open netlink socket", err))
+    };
+
+    if let Err(err) = socket.set_link_up(index, false) {
+        return Err(Error::io(format!("Failed to turn off interface {}", interface), err));
+    }
+
+    // Always try to bring the interface back up, even if the kernel rejected the address
+    let change = socket.set_address(index, mac);
+    let turn_on = socket.set_link_up(index, true);
+
+    if let Err(err) = change {
+        return Err(Error::io(format!("Failed to change MAC address for interface {}", interface), err));
+    }
+
+    return match turn_on {
+        Ok(_) => Ok(()),
+        Err(err) => Err(Error::io(format!("Failed to turn on interface {}", interface), err))
+    };
+}
+
+fn not_found(interface: &str) -> Error {
+    Error::InterfaceNotFound(format!("Interface '{}' doesn't exist", interface))
+}
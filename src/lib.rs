@@ -0,0 +1,53 @@
+//! Random MAC addresses that carry the OUI of a real vendor.
+//!
+//! [`AddressDatabase`] holds the blocks of the IEEE registry, downloaded from the sources configured in a
+//! [`DataSources`] file. Addresses and prefixes are parsed into [`MacAddress`] and [`OuiPrefix`], random addresses
+//! are drawn with [`MacInformation::random_from_prefix`] or [`macaddress::random_local`] and assigned with
+//! [`interface::apply`]. Several interfaces are changed together with [`interface::plan`] and
+//! [`interface::apply_plan`], which leave every interface alone unless each of them got a valid address.
+//! Failures are reported as an [`Error`] of the kind of problem.
+//!
+//! ```no_run
+//! use random_mac::{AddressDatabase, DataSources, MacInformation, OuiPrefix};
+//! use random_mac::vendor::{VendorAliases, VendorQuery};
+//!
+//! let datasource = DataSources::load_or_default("datasource.json")?;
+//! let database = AddressDatabase::load(datasource, String::from("database.bin"))?;
+//!
+//! let prefix = "00:1B:21".parse::<OuiPrefix>()?;
+//! if let Some(block) = database.lookup(&prefix) {
+//!     println!("{} belongs to {}", prefix, block.vendor());
+//! }
+//!
+//! let query = VendorQuery::new(Some("intel"), false)?;
+//! let intel = &database.vendors(&query, &VendorAliases::default())[0];
+//! let address = database.record(intel.positions[0]).random_from_prefix()?;
+//! random_mac::interface::apply(&address, "eth0", "state.json")?;
+//! # Ok::<(), random_mac::Error>(())
+//! ```
+
+#![warn(missing_docs)]
+#![allow(clippy::needless_return)]
+
+pub mod address;
+mod cache;
+mod compact;
+pub mod database;
+pub mod error;
+mod ethtool;
+mod generator;
+pub mod interface;
+pub mod macaddress;
+mod merge;
+mod netlink;
+pub mod record;
+mod state;
+mod storage;
+pub mod vendor;
+
+pub use crate::address::{AddressIssue, MacAddress, OuiPrefix};
+pub use crate::database::AddressDatabase;
+pub use crate::error::{Error, Result};
+pub use crate::macaddress::{DataSource, DataSources, MacInformation};
+pub use crate::merge::ConflictRule;
+pub use crate::record::MacRecord;
@@ -1,76 +1,407 @@
+//! Datasources, the registry formats they are read from and random address generation.
+
 use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
-use rand::Rng;
 use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use minisign_verify::{PublicKey, Signature};
+use reqwest::header;
+use reqwest::StatusCode;
+use crate::address::{MacAddress, OuiPrefix};
+use crate::cache::{HttpCache, Validators};
+use crate::error::{Error, Result};
+use crate::generator::SuffixGenerator;
+use crate::merge::{self, ConflictRule};
 
-pub trait MacInformation: erased_serde::Serialize {
+/// A registry block, in the format of the datasource it was read from or as a `MacRecord`.
+pub trait MacInformation {
 
-    fn prefix(&self) -> String;
+    /// The assigned prefix.
+    fn prefix(&self) -> OuiPrefix;
 
+    /// Name of the organization the block is registered to.
     fn vendor(&self) -> String;
 
+    /// Whether the organization asked the IEEE to keep its name private.
     fn is_private(&self) -> bool;
 
+    /// `MA-L`, `MA-M`, `MA-S`, `CID` or `IAB`, see `block_length`.
     fn block_type(&self) -> String;
 
-    fn random_from_prefix(&self) -> String {
-        let mut rng = rand::thread_rng();
-        let mut mac = self.prefix();
-        for _ in 0..3 {
-            mac.push_str(format!(":{:02X}", rng.gen_range(0..255)).as_str());
-        }
-        return mac;
+    /// Datasource the entry was imported from, if it was recorded.
+    fn source(&self) -> Option<String> {
+        None
     }
 
-}
+    /// Postal address of the organization, only the IEEE registries list it.
+    fn address(&self) -> Option<String> {
+        None
+    }
 
-erased_serde::serialize_trait_object!(MacInformation);
+    /// Generates an address inside this block, the random part is sized by the block type.
+    fn random_from_prefix(&self) -> Result<MacAddress> {
+        return random_from_prefix(&self.prefix(), Some(self.block_type().as_str()));
+    }
+
+}
 
 trait MacData {
 
-    fn convert(data: String) -> Result<Vec<Box<dyn MacInformation>>, String>;
+    fn convert(data: String) -> Result<Vec<Box<dyn MacInformation>>>;
 
 }
 
-#[derive(Serialize, Deserialize)]
+/// The maclookup.app database, used when no datasource is configured.
+pub const DEFAULT_URL: &str = "https://maclookup.app/downloads/json-database/get-db";
+
+/// Registry files shipped by distribution packages, in order of preference.
+const SYSTEM_SOURCES: [(&str, &str); 6] = [
+    ("/usr/share/hwdata/oui.txt", "hwdata"),
+    ("/usr/share/misc/oui.txt", "hwdata"),
+    ("/usr/share/ieee-data/oui.txt", "hwdata"),
+    ("/usr/share/nmap/nmap-mac-prefixes", "nmap"),
+    ("/usr/share/wireshark/manuf", "manuf"),
+    ("/usr/share/wireshark/manuf.txt", "manuf"),
+];
+
+/// Raw registry payload with the validators to revalidate it, or `NotModified` on a 304 response.
+pub(crate) enum Fetched {
+
+    Modified(Vec<u8>, Validators),
+    NotModified,
+
+}
+
+/// A registry to download, with the format it is in and how to verify it.
+#[derive(Serialize, Deserialize, Default)]
 pub struct DataSource {
 
     pub(crate) url: String,
     pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) priority: i32,
+    /// Expected SHA-256 digest of the payload, hex encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sha256: Option<String>,
+    /// URL of a detached minisign signature, `<url>.minisig` when only `publicKey` is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) signature: Option<String>,
+    /// Minisign public key (the base64 `RW...` line) the signature has to verify against.
+    #[serde(default, rename = "publicKey", skip_serializing_if = "Option::is_none")]
+    pub(crate) public_key: Option<String>,
 
 }
 
-impl DataSource {
+/// Every datasource configured in `datasource.json` and how to merge them.
+#[derive(Serialize, Deserialize)]
+pub struct DataSources {
+
+    pub(crate) sources: Vec<DataSource>,
+    #[serde(default)]
+    pub(crate) conflict: ConflictRule,
+
+}
+
+/// The accepted layouts of `datasource.json`: a single source, a list of sources or the full configuration.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DataSourceFile {
+
+    Single(DataSource),
+    List(Vec<DataSource>),
+    Full(DataSources),
+
+}
+
+impl DataSources {
+
+    /// Merges `sources` with the default conflict rule, at least one source is needed.
+    pub fn new(sources: Vec<DataSource>) -> Result<Self> {
+        if sources.is_empty() {
+            return Err(Error::Other(String::from("No datasource given")));
+        }
+        return Ok(DataSources { sources, conflict: ConflictRule::default() });
+    }
+
+    /// Sets how the vendor is picked when several sources register the same prefix.
+    pub fn with_conflict(mut self, conflict: ConflictRule) -> Self {
+        self.conflict = conflict;
+        self
+    }
+
+    /// How the vendor is picked when several sources register the same prefix.
+    pub fn conflict(&self) -> ConflictRule {
+        self.conflict
+    }
+
+    /// Reads a `datasource.json` in any of its layouts: one source, a list of sources or the full configuration.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(Error::io(format!("Failed to read {:?}", path), err))
+        };
+
+        let file: DataSourceFile = match serde_json::from_str(content.as_str()) {
+            Ok(json) => json,
+            Err(err) => return Err(Error::json(format!("{:?}", path), &err))
+        };
+
+        let datasources = match file {
+            DataSourceFile::Single(source) => DataSources::from(source),
+            DataSourceFile::List(sources) => DataSources { sources, conflict: ConflictRule::default() },
+            DataSourceFile::Full(datasources) => datasources
+        };
+
+        if datasources.sources.is_empty() {
+            return Err(Error::parse(format!("{:?}", path), "no datasource configured"));
+        }
+        return Ok(datasources);
+    }
 
-    pub fn from_file(path: &Path) -> Result<Self, String> {
-        let content = fs::read_to_string(path)
-            .expect(&*format!("Failed to read {:?}!", path));
+    /// Reads `path`, creating it if it doesn't exist.
+    ///
+    /// A new configuration prefers a registry installed by the distribution over downloading one, so offline
+    /// machines work out of the box, see `DataSource::discover`. Without one it uses the maclookup.app database.
+    pub fn load_or_default(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            let source = DataSource::discover().unwrap_or_else(|| DataSource::new(DEFAULT_URL, "maclookupapp"));
+            let datasource = DataSources::from(source);
+            datasource.save(path)?;
+            return Ok(datasource);
+        }
+
+        return DataSources::from_file(path.as_ref());
+    }
+
+    /// Whether this is the maclookup.app database alone, the configuration written before registries were
+    /// discovered.
+    pub fn is_default(&self) -> bool {
+        self.sources.len() == 1 && self.sources[0].url == DEFAULT_URL
+    }
+
+    /// The configured sources, in the order of `datasource.json`.
+    pub fn sources(&self) -> &[DataSource] {
+        &self.sources
+    }
+
+    /// Writes the configuration to `path` in the full layout.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let serialize = match serde_json::to_string(self) {
+            Ok(json) => json,
+            Err(_) => return Err(Error::Other(String::from("Failed to serialize JSON")))
+        };
+
+        return match fs::write(path, serialize) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::io(format!("Failed to write {:?}", path), err))
+        };
+    }
+
+    /// SHA-256 of the serialized configuration, changing a source, its priority or the conflict rule changes it.
+    pub fn fingerprint(&self) -> Result<String> {
+        let serialize = match serde_json::to_string(self) {
+            Ok(json) => json,
+            Err(_) => return Err(Error::Other(String::from("Failed to serialize JSON")))
+        };
+        return Ok(Sha256::digest(serialize.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect());
+    }
+
+    /// Fetches every source and merges them into a single list of entries.
+    ///
+    /// With `conditional` the validators in `cache` are sent along, `None` is returned when every
+    /// source answered `304 Not Modified`. Sources that didn't change are downloaded again if any
+    /// other one did, since the merged database has to be rebuilt from all of them. A database built
+    /// from another configuration than this one is always rebuilt.
+    pub(crate) fn fetch_information(&self, cache: &mut HttpCache, conditional: bool) -> Result<Option<Vec<Box<dyn MacInformation>>>> {
+        let fingerprint = self.fingerprint()?;
+        let conditional = conditional && cache.is_built_from(&fingerprint);
+
+        let mut payloads = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            let validators = if conditional { cache.get(&source.url) } else { None };
+            payloads.push(source.fetch(validators)?);
+        }
+
+        if payloads.iter().all(|fetched| matches!(fetched, Fetched::NotModified)) {
+            return Ok(None);
+        }
+
+        let mut fetched = Vec::with_capacity(self.sources.len());
+        for (source, payload) in self.sources.iter().zip(payloads) {
+            let (data, validators) = match payload {
+                Fetched::Modified(data, validators) => (data, validators),
+                Fetched::NotModified => match source.fetch(None)? {
+                    Fetched::Modified(data, validators) => (data, validators),
+                    Fetched::NotModified => return Err(Error::Http {
+                        url: source.url.clone(),
+                        message: String::from("Unexpected 304 response")
+                    })
+                }
+            };
+            source.verify(&data)?;
+            let data = String::from_utf8_lossy(&data).into_owned();
+            match convert(source.name(), data) {
+                Ok(information) => fetched.push((source, information)),
+                // Say which source is broken when several are merged
+                Err(Error::Parse { line, column, message, .. }) => return Err(Error::Parse {
+                    what: source.url.clone(),
+                    line,
+                    column,
+                    message
+                }),
+                Err(err) => return Err(err)
+            }
+            cache.set(&source.url, validators);
+        }
+        let urls = self.sources.iter().map(|source| source.url.as_str()).collect::<Vec<_>>();
+        cache.set_datasource(fingerprint, &urls);
+        return Ok(Some(merge::merge(fetched, self.conflict)));
+    }
+
+    /// Fetches the entries of a database that doesn't exist yet.
+    ///
+    /// A registry installed by the distribution replaces the default datasource, and the configured sources when they
+    /// can't be downloaded, so offline machines set up before registries were discovered still get a database.
+    pub(crate) fn fetch_initial(&self, cache: &mut HttpCache) -> Result<Vec<Box<dyn MacInformation>>> {
+        let system = DataSource::discover().map(DataSources::from);
+        if let (true, Some(system)) = (self.is_default(), &system) {
+            return Ok(system.fetch_information(cache, false)?.unwrap_or_default());
+        }
+
+        return match (self.fetch_information(cache, false), system) {
+            (Err(Error::Http { .. } | Error::Io { .. }), Some(system)) => {
+                Ok(system.fetch_information(cache, false)?.unwrap_or_default())
+            },
+            (information, _) => Ok(information?.unwrap_or_default())
+        };
+    }
+
+}
+
+impl From<DataSource> for DataSources {
+
+    fn from(source: DataSource) -> Self {
+        DataSources {
+            sources: vec![source],
+            conflict: ConflictRule::default()
+        }
+    }
+
+}
+
+impl DataSource {
 
-        match serde_json::from_str(content.as_str()) {
-            Ok(json) => Ok(json),
-            Err(_) => return Err(String::from("Failed to parse JSON"))
+    /// A source without digest or signature, `name` selects the format like in `datasource.json`.
+    pub fn new(url: &str, name: &str) -> Self {
+        DataSource {
+            url: url.to_string(),
+            name: name.to_string(),
+            ..Default::default()
         }
     }
 
+    /// Where the registry is read from: an HTTP(S) URL, a `file://` URL, a path or `-` for stdin.
     pub fn url(&self) -> String {
         self.url.clone()
     }
 
+    /// Format of the registry, like `ieee` or `manuf`.
     pub fn name(&self) -> String {
         self.name.clone()
     }
 
-    pub fn fetch_information(&self) -> Result<Vec<Box<dyn MacInformation>>, String> {
-        return fetch_information(self.clone());
+    /// Rank of the source when several register the same prefix, see `ConflictRule::Priority`.
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Sets the rank of the source, higher wins. Sources default to 0.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Rejects payloads whose SHA-256 digest isn't `digest`, hex encoded.
+    pub fn with_sha256(mut self, digest: &str) -> Self {
+        self.sha256 = Some(digest.to_string());
+        self
+    }
+
+    /// Rejects payloads without a valid minisign signature of `public_key`, the base64 `RW...` line.
+    ///
+    /// The signature is read from `signature`, or from `<url>.minisig` without one.
+    pub fn with_public_key(mut self, public_key: &str, signature: Option<&str>) -> Self {
+        self.public_key = Some(public_key.to_string());
+        self.signature = signature.map(|signature| signature.to_string());
+        self
+    }
+
+    /// Reads the raw registry, sending `validators` as `If-None-Match`/`If-Modified-Since` to HTTP servers.
+    pub(crate) fn fetch(&self, validators: Option<&Validators>) -> Result<Fetched> {
+        return read_source(self.url.as_str(), validators);
+    }
+
+    /// Checks the payload against the configured digest and signature, a source without either always passes.
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        if let Some(expected) = &self.sha256 {
+            let digest = Sha256::digest(data)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>();
+            if !digest.eq_ignore_ascii_case(expected.trim()) {
+                return Err(Error::Other(format!("SHA-256 mismatch, expected {} but got {} ({})", expected, digest, self.url)));
+            }
+        }
+
+        let public_key = match &self.public_key {
+            Some(public_key) => public_key,
+            None if self.signature.is_some() => return Err(Error::Other(format!("Signature given without a public key ({})", self.url))),
+            None => return Ok(())
+        };
+        let public_key = match PublicKey::from_base64(public_key.trim()) {
+            Ok(public_key) => public_key,
+            Err(err) => return Err(Error::parse("minisign public key", err.to_string()))
+        };
+
+        let signature_url = self.signature.clone().unwrap_or_else(|| format!("{}.minisig", self.url));
+        let signature = match read_source(signature_url.as_str(), None)? {
+            Fetched::Modified(signature, _) => signature,
+            Fetched::NotModified => return Err(Error::Http {
+                url: signature_url,
+                message: String::from("Unexpected 304 response for signature")
+            })
+        };
+        let signature = match Signature::decode(String::from_utf8_lossy(&signature).as_ref()) {
+            Ok(signature) => signature,
+            Err(err) => return Err(Error::parse(signature_url, err.to_string()))
+        };
+
+        // Legacy signatures are plain ed25519 over the payload, newer ones sign its BLAKE2b hash
+        return match public_key.verify(data, &signature, true) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::Other(format!("Signature verification failed ({})", self.url)))
+        };
+    }
+
+    /// Looks for a registry installed by the distribution (hwdata, ieee-data, nmap or Wireshark).
+    pub fn discover() -> Option<Self> {
+        return SYSTEM_SOURCES.iter()
+            .find(|(path, _)| Path::new(path).is_file())
+            .map(|(path, name)| DataSource::new(path, name));
     }
 
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct MacLookupApp {
 
-    mac_prefix: String,
+    mac_prefix: OuiPrefix,
     vendor_name: String,
     private: bool,
     block_type: String,
@@ -79,8 +410,8 @@ struct MacLookupApp {
 
 impl MacInformation for MacLookupApp {
 
-    fn prefix(&self) -> String {
-        self.mac_prefix.clone()
+    fn prefix(&self) -> OuiPrefix {
+        self.mac_prefix
     }
 
     fn vendor(&self) -> String {
@@ -99,13 +430,71 @@ impl MacInformation for MacLookupApp {
 
 impl MacData for MacLookupApp {
 
-    fn convert(data: String) -> Result<Vec<Box<(dyn MacInformation)>>, String> {
+    fn convert(data: String) -> Result<Vec<Box<dyn MacInformation>>> {
+        return load_json::<MacLookupApp>(data);
+    }
+
+}
+
+/// A row of the IEEE Registration Authority exports (oui.csv, mam.csv, oui36.csv, cid.csv and iab.csv).
+#[derive(Deserialize)]
+struct IeeeRegistry {
+
+    #[serde(rename = "Registry")]
+    registry: String,
+    #[serde(rename = "Assignment")]
+    assignment: OuiPrefix,
+    #[serde(rename = "Organization Name")]
+    organization_name: String,
+    #[serde(rename = "Organization Address", default)]
+    organization_address: String,
+
+}
+
+impl MacInformation for IeeeRegistry {
+
+    fn prefix(&self) -> OuiPrefix {
+        self.assignment
+    }
+
+    fn vendor(&self) -> String {
+        self.organization_name.clone()
+    }
+
+    fn is_private(&self) -> bool {
+        self.organization_name.eq_ignore_ascii_case("private")
+    }
+
+    fn block_type(&self) -> String {
+        self.registry.clone()
+    }
+
+    fn address(&self) -> Option<String> {
+        Some(self.organization_address.clone()).filter(|address| !address.is_empty())
+    }
+
+}
+
+impl MacData for IeeeRegistry {
+
+    fn convert(data: String) -> Result<Vec<Box<dyn MacInformation>>> {
         let mut result: Vec<Box<dyn MacInformation>> = Vec::new();
-        let json: Vec<MacLookupApp> = match serde_json::from_str(data.as_str()) {
-            Ok(json) => json,
-            Err(_) => return Err(String::from("Failed to parse JSON"))
-        };
-        for entry in json {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        for entry in reader.deserialize::<IeeeRegistry>() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let line = err.position().map(|position| position.line() as usize);
+                    let message = match err.kind() {
+                        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                        _ => err.to_string()
+                    };
+                    return Err(Error::Parse { what: String::from("CSV"), line, column: None, message });
+                }
+            };
             result.push(Box::new(entry));
         }
         return Ok(result);
@@ -113,35 +502,609 @@ impl MacData for MacLookupApp {
 
 }
 
-fn fetch_information(data_source: &DataSource) -> Result<Vec<Box<dyn MacInformation>>, String> {
-    let request = reqwest::blocking::get(data_source.url().as_str());
-    let data = match request {
-        Ok(response) => response.text(),
-        Err(_) => return Err(String::from("Error fetching data"))
+/// An entry of Wireshark's `manuf` file, `00:1B:C5:00:00:00/36<TAB>Convergi<TAB>Converging Systems Inc.`
+struct WiresharkManuf {
+
+    mac_prefix: OuiPrefix,
+    mask: u8,
+    short_name: String,
+    long_name: Option<String>,
+
+}
+
+impl MacInformation for WiresharkManuf {
+
+    fn prefix(&self) -> OuiPrefix {
+        self.mac_prefix
+    }
+
+    fn vendor(&self) -> String {
+        self.long_name.clone().unwrap_or_else(|| self.short_name.clone())
+    }
+
+    fn is_private(&self) -> bool {
+        self.short_name.eq_ignore_ascii_case("private")
+    }
+
+    fn block_type(&self) -> String {
+        block_type_for_length(self.mask).to_string()
+    }
+
+}
+
+impl WiresharkManuf {
+
+    fn parse_line(line: &str) -> Option<Self> {
+        // Older files put the long name in a comment, after a tab or after the padded short name:
+        // `00:00:01<TAB>Xerox<TAB># XEROX CORPORATION`, `00:00:0C<TAB>Cisco    # CISCO SYSTEMS, INC.`
+        let (fields, comment) = match line.split_once('#') {
+            Some((fields, comment)) => (fields, Some(comment.trim()).filter(|comment| !comment.is_empty())),
+            None => (line, None)
+        };
+        let mut fields = fields.split('\t')
+            .map(|field| field.trim())
+            .filter(|field| !field.is_empty());
+
+        let address = fields.next()?;
+        let short_name = fields.next()?.to_string();
+        let long_name = fields.next().or(comment).map(|name| name.to_string());
+
+        let prefix = address.parse::<OuiPrefix>().ok()?;
+        let mask = prefix.length();
+
+        // Only nibble aligned blocks can be written as a prefix, full addresses leave nothing to randomize
+        if mask % 4 != 0 || mask >= 48 {
+            return None;
+        }
+
+        return Some(Self {
+            mac_prefix: prefix,
+            mask,
+            short_name,
+            long_name
+        });
+    }
+
+}
+
+impl MacData for WiresharkManuf {
+
+    fn convert(data: String) -> Result<Vec<Box<dyn MacInformation>>> {
+        let mut result: Vec<Box<dyn MacInformation>> = Vec::new();
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(entry) = WiresharkManuf::parse_line(line) {
+                result.push(Box::new(entry));
+            }
+        }
+        return Ok(result);
+    }
+
+}
+
+/// An entry of nmap's `nmap-mac-prefixes`, `0050C2000 T.L.S.` or `000000 Xerox`.
+struct NmapMacPrefix {
+
+    mac_prefix: OuiPrefix,
+    vendor_name: String,
+
+}
+
+impl MacInformation for NmapMacPrefix {
+
+    fn prefix(&self) -> OuiPrefix {
+        self.mac_prefix
+    }
+
+    fn vendor(&self) -> String {
+        self.vendor_name.clone()
+    }
+
+    fn is_private(&self) -> bool {
+        self.vendor_name.eq_ignore_ascii_case("private")
+    }
+
+    fn block_type(&self) -> String {
+        block_type_for_length(self.mac_prefix.length()).to_string()
+    }
+
+}
+
+impl MacData for NmapMacPrefix {
+
+    fn convert(data: String) -> Result<Vec<Box<dyn MacInformation>>> {
+        let mut result: Vec<Box<dyn MacInformation>> = Vec::new();
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (prefix, vendor) = match line.split_once(char::is_whitespace) {
+                Some(entry) => entry,
+                None => continue
+            };
+            let prefix = match prefix.parse::<OuiPrefix>() {
+                Ok(prefix) => prefix,
+                Err(_) => continue
+            };
+            result.push(Box::new(NmapMacPrefix {
+                mac_prefix: prefix,
+                vendor_name: vendor.trim().to_string()
+            }));
+        }
+        return Ok(result);
+    }
+
+}
+
+/// An entry of the IEEE `oui.txt` listing shipped by hwdata and ieee-data.
+///
+/// ```text
+/// 00-22-72   (hex)        American Micro-Fuel Device Corp.
+/// 002272     (base 16)    American Micro-Fuel Device Corp.
+///                         2181 Buchanan Loop
+///                         Ferndale  WA  98248
+/// ```
+struct HwdataOui {
+
+    mac_prefix: OuiPrefix,
+    vendor_name: String,
+    address: Vec<String>,
+
+}
+
+impl MacInformation for HwdataOui {
+
+    fn prefix(&self) -> OuiPrefix {
+        self.mac_prefix
+    }
+
+    fn vendor(&self) -> String {
+        self.vendor_name.clone()
+    }
+
+    fn is_private(&self) -> bool {
+        self.vendor_name.eq_ignore_ascii_case("private")
+    }
+
+    fn block_type(&self) -> String {
+        String::from("MA-L")
+    }
+
+    fn address(&self) -> Option<String> {
+        Some(self.address.join(", ")).filter(|address| !address.is_empty())
+    }
+
+}
+
+impl MacData for HwdataOui {
+
+    fn convert(data: String) -> Result<Vec<Box<dyn MacInformation>>> {
+        let mut result: Vec<HwdataOui> = Vec::new();
+        let mut in_entry = false;
+        for line in data.lines() {
+            if let Some((prefix, vendor)) = line.split_once("(hex)") {
+                let prefix = prefix.trim().parse::<OuiPrefix>();
+                in_entry = prefix.is_ok();
+                if let Ok(prefix) = prefix {
+                    result.push(HwdataOui {
+                        mac_prefix: prefix,
+                        vendor_name: vendor.trim().to_string(),
+                        address: Vec::new()
+                    });
+                }
+            } else if line.trim().is_empty() {
+                in_entry = false;
+            } else if in_entry && !line.contains("(base 16)") {
+                if let Some(entry) = result.last_mut() {
+                    entry.address.push(line.trim().to_string());
+                }
+            }
+        }
+        return Ok(result.into_iter()
+            .map(|entry| Box::new(entry) as Box<dyn MacInformation>)
+            .collect());
+    }
+
+}
+
+fn load_json<T: MacInformation + DeserializeOwned + 'static>(data: String) -> Result<Vec<Box<dyn MacInformation>>> {
+    let json: Vec<T> = match serde_json::from_str(data.as_str()) {
+        Ok(json) => json,
+        Err(err) => return Err(Error::json("JSON", &err))
+    };
+    return Ok(json.into_iter()
+        .map(|entry| Box::new(entry) as Box<dyn MacInformation>)
+        .collect());
+}
+
+/// Reads the raw registry from an HTTP(S) URL, a `file://` URL, a filesystem path or `-` for stdin.
+fn read_source(url: &str, validators: Option<&Validators>) -> Result<Fetched> {
+    if url == "-" {
+        let mut data = Vec::new();
+        return match io::stdin().read_to_end(&mut data) {
+            Ok(_) => Ok(Fetched::Modified(data, Validators::default())),
+            Err(err) => Err(Error::io("Failed to read stdin", err))
+        };
+    }
+
+    if url.starts_with("file://") {
+        let path = match reqwest::Url::parse(url).ok().and_then(|url| url.to_file_path().ok()) {
+            Some(path) => path,
+            None => return Err(Error::parse("datasource URL", format!("invalid file URL {}", url)))
+        };
+        return match fs::read(&path) {
+            Ok(data) => Ok(Fetched::Modified(data, Validators::default())),
+            Err(err) => Err(Error::io(format!("Failed to read {:?}", path), err))
+        };
+    }
+
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return match fs::read(url) {
+            Ok(data) => Ok(Fetched::Modified(data, Validators::default())),
+            Err(err) => Err(Error::io(format!("Failed to read {:?}", url), err))
+        };
+    }
+
+    let http_error = |message: String| Error::Http { url: url.to_string(), message };
+
+    let mut request = reqwest::blocking::Client::new().get(url);
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = match request.send() {
+        Ok(response) => response,
+        Err(err) => return Err(http_error(format!("Error fetching data: {}", err.without_url())))
     };
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(http_error(format!("Server answered {}", response.status())));
+    }
 
-    return match data {
-        Ok(data) => convert(data_source.name(), data),
-        Err(_) => Err(String::from("Error converting data"))
+    let header = |name: header::HeaderName| response.headers().get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let validators = Validators {
+        etag: header(header::ETAG),
+        last_modified: header(header::LAST_MODIFIED)
+    };
+
+    return match response.bytes() {
+        Ok(data) => Ok(Fetched::Modified(data.to_vec(), validators)),
+        Err(err) => Err(http_error(format!("Error reading response: {}", err.without_url())))
     };
 }
 
-pub fn convert(source_name: String, data: String) -> Result<Vec<Box<dyn MacInformation>>, String> {
+/// Parses a whole registry in the format of the source `source_name`.
+pub fn convert(source_name: String, data: String) -> Result<Vec<Box<dyn MacInformation>>> {
     return match source_name.to_lowercase().as_str() {
         "maclookupapp" => MacLookupApp::convert(data),
-        _ => Err(String::from("Invalid source name"))
+        "ieee" => IeeeRegistry::convert(data),
+        "manuf" | "wireshark" => WiresharkManuf::convert(data),
+        "nmap" => NmapMacPrefix::convert(data),
+        "hwdata" | "oui.txt" => HwdataOui::convert(data),
+        _ => Err(Error::Other(format!("Invalid source name {}", source_name)))
     };
 }
 
-pub fn verify_prefix(prefix: &String) -> Result<(), String> {
-    let prefix = prefix.replace(":", "");
-    if prefix.len() != 6 {
-        return Err(String::from("Invalid prefix length"));
+/// Checks a prefix given for `random prefix`, which has to be a unicast MA-L, MA-M or MA-S block.
+pub fn verify_prefix(prefix: &OuiPrefix) -> Result<()> {
+    if !matches!(prefix.length(), 24 | 28 | 36) {
+        return Err(Error::InvalidMac(format!("Invalid prefix length {}, expected 24, 28 or 36 bits", prefix.length())));
     }
-    for character in prefix.chars() {
-        if !character.is_ascii_hexdigit() {
-            return Err(String::from("Invalid prefix character"));
-        }
+    if prefix.is_multicast() {
+        return Err(Error::InvalidMac(format!("{} is a multicast prefix, interfaces only accept unicast addresses", prefix)));
     }
     return Ok(());
-}
\ No newline at end of file
+}
+
+/// Length in bits of the prefix assigned for a registry block type.
+pub fn block_length(block_type: &str) -> Option<u8> {
+    return match block_type.to_uppercase().as_str() {
+        "MA-L" | "CID" => Some(24),
+        "MA-M" => Some(28),
+        "MA-S" | "IAB" => Some(36),
+        _ => None
+    };
+}
+
+/// Registry block type of a prefix that is `length` bits long.
+pub fn block_type_for_length(length: u8) -> &'static str {
+    return match length {
+        24 => "MA-L",
+        28 => "MA-M",
+        36 => "MA-S",
+        _ => "Unknown"
+    };
+}
+
+/// Generates a 48-bit address that keeps the first bits of `prefix` and randomizes the rest.
+///
+/// The kept length is the block length of `block_type` when known, so prefixes written with
+/// trailing zeros (`00:1B:C5:00:00:00` for a MA-S block) still leave the right amount of room.
+pub fn random_from_prefix(prefix: &OuiPrefix, block_type: Option<&str>) -> Result<MacAddress> {
+    let length = match block_type.and_then(block_length) {
+        Some(length) if length <= prefix.length() => length,
+        _ => prefix.length()
+    };
+
+    let block = prefix.truncate(length);
+    if length >= 48 {
+        return Err(Error::InvalidMac(format!("{} is a whole address, it leaves nothing to randomize", block)));
+    }
+    // With at least one random bit and the I/G bit clear, some address of the block is always accepted
+    if block.is_multicast() {
+        return Err(Error::InvalidMac(format!("{} is a multicast prefix, interfaces only accept unicast addresses", block)));
+    }
+
+    let network = block.network().to_bits();
+    let mut generator = SuffixGenerator::new();
+    loop {
+        let mut address = MacAddress::from_bits(network | generator.suffix(48 - length));
+        // A prefix shorter than an octet leaves the I/G bit to chance
+        if length < 8 {
+            address.0[0] &= 0xFE;
+        }
+        // Only reachable from 00:00:00, draw again instead of handing out an address the kernel refuses
+        if address.issues(false).is_empty() {
+            return Ok(address);
+        }
+    }
+}
+
+/// Generates a unicast address with the locally administered bit set, like the private addresses of phones.
+///
+/// `nibble` fixes the second hex digit to 2, 6, A or E, otherwise it is one of them at random.
+pub fn random_local(nibble: Option<u8>) -> MacAddress {
+    let address = MacAddress::from_bits(SuffixGenerator::new().suffix(48));
+    return match nibble {
+        Some(nibble) => {
+            let mut octets = address.octets();
+            octets[0] = (octets[0] & 0xF0) | nibble;
+            MacAddress(octets)
+        },
+        None => address.into_local_unicast()
+    };
+}
+
+/// Parses the second hex digit of a locally administered unicast address, written as `x2`, `2`, `0x2` and so on.
+pub fn parse_local_nibble(value: &str) -> Result<u8> {
+    let digit = value.trim_start_matches("0x").trim_start_matches(['x', 'X']);
+    return match u8::from_str_radix(digit, 16) {
+        Ok(nibble) if digit.len() == 1 && nibble & 0x03 == 0x02 => Ok(nibble),
+        _ => Err(Error::InvalidMac(String::from("The first octet of a locally administered unicast address ends in 2, 6, A or E")))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::address::OuiPrefix;
+    use crate::error::Error;
+    use crate::merge::ConflictRule;
+    use super::{convert, parse_local_nibble, random_from_prefix, random_local, verify_prefix};
+    use super::{DataSource, DataSources, DEFAULT_URL};
+
+    /// Converts `data` and lists every entry as `(prefix, vendor, block type, address)`.
+    fn entries(source_name: &str, data: &str) -> Vec<(String, String, String, Option<String>)> {
+        convert(source_name.to_string(), data.to_string()).unwrap()
+            .iter()
+            .map(|entry| (entry.prefix().to_string(), entry.vendor(), entry.block_type(), entry.address()))
+            .collect()
+    }
+
+    fn entry(prefix: &str, vendor: &str, block_type: &str, address: Option<&str>) -> (String, String, String, Option<String>) {
+        (prefix.to_string(), vendor.to_string(), block_type.to_string(), address.map(|address| address.to_string()))
+    }
+
+    #[test]
+    fn maclookupapp_json() {
+        let data = r#"[
+            {"macPrefix": "00:1B:21", "vendorName": "Intel Corporate", "private": false, "blockType": "MA-L"},
+            {"macPrefix": "8C:1F:64:F2:1", "vendorName": "Specific Co", "private": true, "blockType": "MA-S"}
+        ]"#;
+        assert_eq!(entries("maclookupapp", data), vec![
+            entry("00:1B:21", "Intel Corporate", "MA-L", None),
+            entry("8C:1F:64:F2:1", "Specific Co", "MA-S", None)
+        ]);
+        assert!(convert(String::from("maclookupapp"), String::from("[{\"macPrefix\": 1}]")).is_err());
+    }
+
+    #[test]
+    fn ieee_csv() {
+        let data = "Registry,Assignment,Organization Name,Organization Address\n\
+            MA-L,001B21,Intel Corporate,\"Lot 8, Jalan Hi-Tech 2/3  Kulim Kedah  MY 09000 \"\n\
+            MA-M,8C1F64F,Medium Co,\n\
+            MA-S,8C1F64F21,Private,\n";
+        assert_eq!(entries("ieee", data), vec![
+            entry("00:1B:21", "Intel Corporate", "MA-L", Some("Lot 8, Jalan Hi-Tech 2/3  Kulim Kedah  MY 09000")),
+            entry("8C:1F:64:F", "Medium Co", "MA-M", None),
+            entry("8C:1F:64:F2:1", "Private", "MA-S", None)
+        ]);
+        assert!(convert(String::from("ieee"), String::from("Registry,Assignment,Organization Name\nMA-L,XYZ,Broken\n")).is_err());
+    }
+
+    #[test]
+    fn wireshark_manuf() {
+        let data = "# Wireshark manuf\n\
+            00:00:01\tXerox\t# XEROX CORPORATION\n\
+            00:00:02\tXerox\n\
+            00:00:0C\tCisco                  # CISCO SYSTEMS, INC.\n\
+            00:1B:21\tIntel\tIntel Corporate\n\
+            00:1B:C5:00:00:00/36\tConvergi\tConverging Systems Inc.\n\
+            00:1B:C5:00:00:00/25\tUnaligned\n\
+            01:80:C2:00:00:00\tSpanning-tree-(for-bridges)_00\n";
+        assert_eq!(entries("manuf", data), vec![
+            entry("00:00:01", "XEROX CORPORATION", "MA-L", None),
+            entry("00:00:02", "Xerox", "MA-L", None),
+            entry("00:00:0C", "CISCO SYSTEMS, INC.", "MA-L", None),
+            entry("00:1B:21", "Intel Corporate", "MA-L", None),
+            entry("00:1B:C5:00:0", "Converging Systems Inc.", "MA-S", None)
+        ]);
+    }
+
+    #[test]
+    fn nmap_mac_prefixes() {
+        let data = "# nmap\n000000 Xerox\n0050C2000 T.L.S.\n001B21 Intel Corporate\ninvalid line\n";
+        assert_eq!(entries("nmap", data), vec![
+            entry("00:00:00", "Xerox", "MA-L", None),
+            entry("00:50:C2:00:0", "T.L.S.", "MA-S", None),
+            entry("00:1B:21", "Intel Corporate", "MA-L", None)
+        ]);
+    }
+
+    #[test]
+    fn hwdata_oui_txt() {
+        let data = "OUI/MA-L\t\t\tOrganization\n\
+            company_id\t\t\tOrganization\n\
+            \t\t\t\tAddress\n\
+            \n\
+            00-22-72   (hex)\t\tAmerican Micro-Fuel Device Corp.\n\
+            002272     (base 16)\t\tAmerican Micro-Fuel Device Corp.\n\
+            \t\t\t\t2181 Buchanan Loop\n\
+            \t\t\t\tFerndale  WA  98248\n\
+            \t\t\t\tUS\n\
+            \n\
+            00-1B-21   (hex)\t\tIntel Corporate\n\
+            001B21     (base 16)\t\tIntel Corporate\n";
+        assert_eq!(entries("hwdata", data), vec![
+            entry("00:22:72", "American Micro-Fuel Device Corp.", "MA-L", Some("2181 Buchanan Loop, Ferndale  WA  98248, US")),
+            entry("00:1B:21", "Intel Corporate", "MA-L", None)
+        ]);
+    }
+
+    #[test]
+    fn unknown_source_name() {
+        assert!(convert(String::from("unknown"), String::new()).is_err());
+    }
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn sha256_is_checked() {
+        assert!(DataSource::new("oui.csv", "ieee").verify(b"abc").is_ok());
+
+        let source = DataSource::new("oui.csv", "ieee").with_sha256(&ABC_SHA256.to_uppercase());
+        assert!(source.verify(b"abc").is_ok());
+        assert!(matches!(source.verify(b"abd"), Err(Error::Other(message)) if message.starts_with("SHA-256 mismatch")));
+    }
+
+    #[test]
+    fn signatures_need_a_valid_public_key() {
+        let mut source = DataSource::new("oui.csv", "ieee");
+        source.signature = Some(String::from("oui.csv.minisig"));
+        assert!(matches!(source.verify(b"abc"), Err(Error::Other(_))));
+
+        let source = DataSource::new("oui.csv", "ieee").with_public_key("not a key", None);
+        assert!(matches!(source.verify(b"abc"), Err(Error::Parse { .. })));
+    }
+
+    const SIGNED: &[u8] = b"MA-L,001B21,Intel Corporate,Kulim\n";
+    const PUBLIC_KEY: &str = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const SIGNATURE: &str = "untrusted comment: test key\n\
+        RUQBAgMEBQYHCPQAP4/kCoUeu3CYjzb/KiFN+qElTU9SpFVae8s2y1dv7dOyehO2sJXc5ASVpMwjiimEQAyzTmfoKQvwVl6jSwE=\n\
+        trusted comment: timestamp:0\tfile:oui.csv\n\
+        PgF5fjmyS88sVQetpikJtW/LYMacLI8gCT9/TbtR2Hvo5MKoh7RtewXeY+kM6PQ16QoqXZLg5D5poTyxnd3nAQ==\n";
+
+    #[test]
+    fn minisign_signatures_are_checked() {
+        let path = std::env::temp_dir().join(format!("random-mac-test-{}.minisig", std::process::id()));
+        std::fs::write(&path, SIGNATURE).unwrap();
+
+        let source = DataSource::new("oui.csv", "ieee").with_public_key(PUBLIC_KEY, path.to_str());
+        let signed = source.verify(SIGNED);
+        let tampered = source.verify(b"MA-L,001B21,Evil Corporate,Kulim\n");
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(signed.is_ok());
+        assert!(matches!(tampered, Err(Error::Other(message)) if message.starts_with("Signature verification failed")));
+    }
+
+    #[test]
+    fn built_configuration_matches_datasource_json() {
+        assert!(DataSources::new(Vec::new()).is_err());
+
+        let datasources = DataSources::new(vec![
+            DataSource::new("https://standards-oui.ieee.org/oui/oui.csv", "ieee").with_priority(10).with_sha256("ab12"),
+            DataSource::new("manuf", "manuf").with_public_key(PUBLIC_KEY, None)
+        ]).unwrap().with_conflict(ConflictRule::LongestName);
+        assert_eq!(serde_json::to_value(&datasources).unwrap(), serde_json::json!({
+            "sources": [
+                {"url": "https://standards-oui.ieee.org/oui/oui.csv", "name": "ieee", "priority": 10, "sha256": "ab12"},
+                {"url": "manuf", "name": "manuf", "priority": 0, "publicKey": PUBLIC_KEY}
+            ],
+            "conflict": "longest-name"
+        }));
+        assert!(!datasources.is_default());
+        assert!(DataSources::from(DataSource::new(DEFAULT_URL, "maclookupapp")).is_default());
+    }
+
+    #[test]
+    fn random_addresses_stay_in_the_block() {
+        let ma_s = "8C:1F:64:F2:1".parse::<OuiPrefix>().unwrap();
+        for _ in 0..1000 {
+            let address = random_from_prefix(&ma_s, None).unwrap();
+            assert_eq!(OuiPrefix::from(address).truncate(36), ma_s);
+        }
+
+        // A MA-S block written with trailing zeros keeps only its 36 bits
+        let padded = "00:1B:C5:00:00:00".parse::<OuiPrefix>().unwrap();
+        let address = random_from_prefix(&padded, Some("MA-S")).unwrap();
+        assert_eq!(OuiPrefix::from(address).truncate(36).to_string(), "00:1B:C5:00:0");
+    }
+
+    #[test]
+    fn prefixes_without_a_valid_address_are_refused() {
+        for prefix in ["00:00:00:00:00:00", "00:1B:21:3A:4F:5C", "01:00:5E"] {
+            assert!(random_from_prefix(&prefix.parse().unwrap(), None).is_err(), "{}", prefix);
+        }
+        // The only address left to draw besides the refused all-zero one
+        let almost_zero = "00:00:00:00:00:00/47".parse::<OuiPrefix>().unwrap();
+        assert_eq!(random_from_prefix(&almost_zero, None).unwrap().to_string(), "00:00:00:00:00:01");
+    }
+
+    #[test]
+    fn local_nibbles_are_parsed() {
+        for (value, nibble) in [("x2", 0x2), ("6", 0x6), ("0xA", 0xA), ("xe", 0xE), ("XE", 0xE)] {
+            assert_eq!(parse_local_nibble(value).unwrap(), nibble, "{}", value);
+        }
+        for value in ["", "x", "x0", "x3", "xB", "x22", "g"] {
+            assert!(parse_local_nibble(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn local_addresses_are_unicast_and_locally_administered() {
+        for _ in 0..1000 {
+            let address = random_local(None);
+            assert!(address.is_locally_administered() && !address.is_multicast(), "{}", address);
+        }
+        for nibble in [0x2, 0x6, 0xA, 0xE] {
+            for _ in 0..100 {
+                let address = random_local(Some(nibble));
+                assert_eq!(address.octets()[0] & 0x0F, nibble, "{}", address);
+                assert!(address.issues(false).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn prefix_lengths_of_every_block_type_are_accepted() {
+        for prefix in ["00:1B:21", "8C:1F:64:F", "8C:1F:64:F2:1"] {
+            assert!(verify_prefix(&prefix.parse().unwrap()).is_ok(), "{}", prefix);
+        }
+        for prefix in ["00:1B", "00:1B:21:3A", "00:1B:21:3A:4F:5C", "01:00:5E"] {
+            assert!(verify_prefix(&prefix.parse().unwrap()).is_err(), "{}", prefix);
+        }
+    }
+}
@@ -1,191 +1,179 @@
+#![allow(clippy::needless_return)]
+
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
-use std::process::Command;
+use std::process;
 use std::string::ToString;
 use directories::{BaseDirs};
-use rand::Rng;
-use crate::macaddress::{DataSource, MacInformation};
+use random_mac::{interface, macaddress, vendor};
+use random_mac::{AddressDatabase, AddressIssue, DataSources, Error, MacAddress, MacInformation, OuiPrefix};
+use random_mac::database::legacy_database;
+use random_mac::interface::PlannedAddress;
+use random_mac::vendor::{BlockFilter, VendorAliases, VendorMatch, VendorQuery};
+
+/// Why a command failed: an error `main` still has to print, or the exit code of errors that were printed already
+/// by a command that went on with its other interfaces.
+enum Failure {
 
-mod macaddress;
+    Error(Error),
+    Reported(i32),
 
-struct AddressDatabase {
-    path: String,
-    information: Vec<Box<dyn MacInformation>>
 }
 
-impl AddressDatabase {
+impl From<Error> for Failure {
 
-    fn new(path: String, information: Vec<Box<dyn MacInformation>>) -> Self {
-        Self {
-            path,
-            information
-        }
-    }
-
-    fn lookup(&self, mac: &str) -> Option<&Box<dyn MacInformation>> {
-        for info in &self.information {
-            if mac.starts_with(info.prefix().as_str()) {
-                return Some(info);
-            }
-        }
-        return None;
+    fn from(error: Error) -> Self {
+        Failure::Error(error)
     }
 
-    fn lookup_vendor(&self, vendor: &str) -> Option<&Box<dyn MacInformation>> {
-        let vendor = vendor.to_lowercase();
-        for info in &self.information {
-            if info.vendor().to_lowercase().contains(&vendor) {
-                return Some(info);
-            }
-        }
-        return None;
-    }
-
-    fn save(&self) -> Result<(), String> {
-        let serialize = match serde_json::to_string(&self.information) {
-            Ok(json) => json,
-            Err(_) => return Err(String::from("Failed to serialize JSON"))
-        };
-
-        return match fs::write(&self.path, serialize) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(String::from("Failed to write JSON"))
-        };
-    }
+}
 
-    fn random_from_prefix(prefix: &str) -> String {
-        let mut rng = rand::thread_rng();
-        let mut mac = String::from(prefix);
-        for _ in 0..3 {
-            mac.push_str(format!(":{:02X}", rng.gen_range(0..255)).as_str());
-        }
-        return mac;
+fn main() {
+    match run() {
+        Ok(_) => (),
+        Err(Failure::Error(error)) => {
+            eprintln!("{}", error);
+            process::exit(error.exit_code());
+        },
+        Err(Failure::Reported(code)) => process::exit(code)
     }
-
 }
 
-fn main() {
+fn run() -> Result<(), Failure> {
 
     let cli = build_cli().get_matches();
 
-    let app_dir = app_dir();
-    if !Path::new(&app_dir).exists(){
-        fs::create_dir_all(app_dir).expect("Failed to create app directory");
-    }
-
     let datasource = match  cli.get_one::<String>("datasource") {
         Some(datasource) => datasource.to_string(),
-        None => datasource()
+        None => datasource()?
     };
 
     let database = match cli.get_one::<String>("database") {
         Some(database) => database.to_string(),
-        None => database()
+        None => database()?
+    };
+
+    let state = match cli.get_one::<String>("state") {
+        Some(state) => state.to_string(),
+        None => state()?
+    };
+
+    let aliases = match cli.get_one::<String>("aliases") {
+        Some(aliases) => aliases.to_string(),
+        None => aliases()?
     };
 
     match cli.subcommand() {
         Some(("update", _)) => {
-            update(datasource.clone(), database.clone()).unwrap();
-            return;
+            update(datasource.clone(), database.clone())?;
         },
         Some(("random", sub_matches)) => {
             match sub_matches.subcommand() {
                 Some(("prefix", sub_matches)) => {
-                    let prefix = match sub_matches.get_one::<String>("prefix") {
+                    let prefix = match sub_matches.get_one::<OuiPrefix>("prefix") {
                         Some(prefix) => prefix,
                         None => {
-                            println!("No prefix given!");
-                            return;
+                            return Err(Error::Other(String::from("No prefix given!")).into());
                         }
                     };
 
-                    let database = match setup_data(datasource.clone(), database.clone()) {
-                        Ok(database) => database,
-                        Err(error) => {
-                            println!("{}", error);
-                            return;
-                        }
-                    };
+                    let database = setup_data(datasource.clone(), database.clone())?;
 
                     let interfaces = sub_matches.get_many::<String>("interface")
                         .unwrap_or_default().map(|v| v.to_string()).collect::<Vec<_>>();
 
-                    match macaddress::verify_prefix(&prefix) {
-                        Ok(_) => (),
-                        Err(err) => {
-                            println!("{}", err);
-                            return;
-                        }
-                    }
+                    macaddress::verify_prefix(prefix)?;
 
                     if interfaces.is_empty() {
                         println!("Generating random MAC address with prefix {}...", prefix);
-                        println!("Random MAC address: {}", AddressDatabase::random_from_prefix(&prefix));
-                        return;
+                        let random_mac = macaddress::random_from_prefix(prefix, None)?;
+                        println!("Random MAC address: {}", random_mac);
+                        print_warnings(&random_mac, &interface::check(&random_mac, true)?);
+                        return Ok(());
                     }
 
                     if !is_root() {
-                        println!("You need to be root to run this command!");
-                        return;
+                        return Err(Error::Permission(String::from("You need to be root to run this command!")).into());
                     }
 
-                    let mac = match database.lookup(prefix) {
-                        Some(information) => information,
-                        None => {
-                            println!("No vendor found with prefix {}!", prefix);
-                            return;
-                        }
-                    };
-
-                    for interface in &interfaces {
-                        update_mac_by_info(mac, interface);
+                    if database.lookup(prefix).is_none() {
+                        return Err(Error::Other(format!("No vendor found with prefix {}!", prefix)).into());
                     }
 
+                    // Keeps every bit given, a MA-M or MA-S prefix inside a larger block stays in its own block
+                    let plan = interface::plan(&interfaces, true, |_| macaddress::random_from_prefix(prefix, None));
+                    apply_plan(plan, &state)?;
+
                 },
                 Some(("vendor", sub_matches)) => {
                     let vendor = match sub_matches.get_one::<String>("vendor") {
                         Some(vendor) => vendor,
                         None => {
-                            println!("No vendor given!");
-                            return;
+                            return Err(Error::Other(String::from("No vendor given!")).into());
                         }
                     };
 
-                    let database = match setup_data(datasource.clone(), database.clone()) {
-                        Ok(database) => database,
-                        Err(error) => {
-                            println!("{}", error);
-                            return;
-                        }
-                    };
+                    let database = setup_data(datasource.clone(), database.clone())?;
 
                     let interfaces = sub_matches.get_many::<String>("interface")
                         .unwrap_or_default().map(|v| v.to_string()).collect::<Vec<_>>();
 
-                    let mac = match database.lookup_vendor(vendor) {
-                        Some(information) => information,
-                        None => {
-                            println!("No vendor found with name {}!", vendor);
-                            return;
-                        }
+                    let aliases = VendorAliases::load(&aliases)?;
+
+                    let pick = sub_matches.get_one::<usize>("pick").copied();
+                    let vendors = database.vendors(&VendorQuery::new(Some(vendor), false)?, &aliases);
+                    let vendor = resolve_vendor(&vendors, vendor, pick)?;
+
+                    let filter = BlockFilter {
+                        large_only: sub_matches.get_flag("ma-l-only"),
+                        exclude_private: sub_matches.get_flag("no-private")
                     };
+                    let weighted = sub_matches.get_flag("weighted");
+                    let blocks = database.blocks(vendor, &filter);
+                    if blocks.is_empty() {
+                        return Err(Error::Other(format!("No block of {} is left after filtering!", vendor.name)).into());
+                    }
 
                     if interfaces.is_empty() {
-                        let random_mac = mac.random_from_prefix();
+                        let mac = vendor::choose_block(&blocks, weighted)?;
+                        let random_mac = mac.random_from_prefix()?;
                         println!("Random MAC address: {}", random_mac);
-                        return;
+                        print_warnings(&random_mac, &interface::check(&random_mac, true)?);
+                        return Ok(());
                     }
 
                     if !is_root() {
-                        println!("You need to be root to run this command!");
-                        return;
+                        return Err(Error::Permission(String::from("You need to be root to run this command!")).into());
                     }
 
-                    println!("Generating random MAC address with vendor {}...", mac.vendor());
-                    for interface in &interfaces {
-                        update_mac_by_info(mac, interface);
+                    println!("Generating random MAC address with vendor {}...", vendor.name);
+                    // Every interface draws its own block, so they don't all share one OUI
+                    let plan = interface::plan(&interfaces, true, |_| {
+                        vendor::choose_block(&blocks, weighted)?.random_from_prefix()
+                    });
+                    apply_plan(plan, &state)?;
+
+                },
+                Some(("local", sub_matches)) => {
+                    let interfaces = sub_matches.get_many::<String>("interface")
+                        .unwrap_or_default().map(|v| v.to_string()).collect::<Vec<_>>();
+                    let nibble = sub_matches.get_one::<u8>("nibble").copied();
+
+                    if interfaces.is_empty() {
+                        println!("Random MAC address: {}", macaddress::random_local(nibble));
+                        return Ok(());
+                    }
+
+                    if !is_root() {
+                        return Err(Error::Permission(String::from("You need to be root to run this command!")).into());
                     }
 
+                    println!("Generating locally administered MAC address for interface {}...", interfaces.join(", "));
+                    let plan = interface::plan(&interfaces, false, |_| Ok(macaddress::random_local(nibble)));
+                    apply_plan(plan, &state)?;
+
                 },
                 Some(("interface", sub_matches)) => {
                     let interfaces = sub_matches.get_many::<String>("interface")
@@ -194,38 +182,82 @@ fn main() {
                     let change = match sub_matches.get_one::<bool>("change") {
                         Some(change) => change,
                         None => {
-                            println!("No change given!");
-                            return;
+                            return Err(Error::Other(String::from("No change given!")).into());
                         }
                     };
 
-                    let database = match setup_data(datasource.clone(), database.clone()) {
-                        Ok(database) => database,
-                        Err(error) => {
-                            println!("{}", error);
-                            return;
-                        }
-                    };
+                    let database = setup_data(datasource.clone(), database.clone())?;
 
                     if interfaces.is_empty() {
-                        println!("No interfaces given!");
-                        return;
+                        return Err(Error::Other(String::from("No interfaces given!")).into());
                     }
 
                     if !is_root() && *change {
-                        println!("You need to be root to run this command!");
-                        return;
+                        return Err(Error::Permission(String::from("You need to be root to run this command!")).into());
                     }
 
-                    random_interface(&database, interfaces, *change)
+                    println!("Generating random MAC address for interface {}...", interfaces.join(", "));
+                    let plan = interface::plan(&interfaces, true, |name| interface::random_vendor_address(&database, name, &state));
+                    if *change {
+                        apply_plan(plan, &state)?;
+                    } else {
+                        report_plan(&plan, true)?;
+                    }
 
                 },
                 _ => unreachable!("This should not happen!")
             }
         },
+        Some(("vendors", sub_matches)) => {
+            let query = VendorQuery::new(sub_matches.get_one::<String>("query").map(|query| query.as_str()), sub_matches.get_flag("regex"))?;
+
+            let database = setup_data(datasource.clone(), database.clone())?;
+
+            let aliases = VendorAliases::load(&aliases)?;
+
+            let vendors = database.vendors(&query, &aliases);
+            if vendors.is_empty() {
+                return Err(Error::Other(String::from("No vendor found!")).into());
+            }
+            print_vendors(&vendors);
+        },
+        Some(("lookup", sub_matches)) => {
+            let addresses = sub_matches.get_many::<String>("address")
+                .unwrap_or_default().map(|v| v.to_string()).collect::<Vec<_>>();
+
+            let database = setup_data(datasource.clone(), database.clone())?;
+
+            lookup(&database, addresses)?;
+        },
+        Some(("export", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("file").map(|file| file.as_str()).unwrap_or("-");
+            export(datasource.clone(), database.clone(), file)?;
+        },
+        Some(("import", sub_matches)) => {
+            let file = match sub_matches.get_one::<String>("file") {
+                Some(file) => file,
+                None => {
+                    return Err(Error::Other(String::from("No file given!")).into());
+                }
+            };
+            import(datasource.clone(), database.clone(), file)?;
+        },
+        Some(("restore", sub_matches)) => {
+            let interfaces = sub_matches.get_many::<String>("interface")
+                .unwrap_or_default().map(|v| v.to_string()).collect::<Vec<_>>();
+
+            if !is_root() {
+                return Err(Error::Permission(String::from("You need to be root to run this command!")).into());
+            }
+
+            let permanent = sub_matches.get_flag("permanent");
+
+            restore(&interfaces, permanent, &state)?;
+        },
         _ => unreachable!("This should not happen!")
     }
 
+    return Ok(());
 }
 
 fn build_cli() -> clap::Command {
@@ -244,10 +276,27 @@ fn build_cli() -> clap::Command {
                     clap::command!("vendor")
                         .about("Generates a random MAC address from a vendor")
                         .arg(
-                            clap::arg!(<vendor> "Vendor to use")
+                            clap::arg!(<vendor> "Vendor to use, matched like in the vendors command")
                                 .required(true)
                                 .index(1)
                         )
+                        .arg(
+                            clap::arg!(-p --pick <N> "Vendor to use when several match, numbered as listed by the vendors command")
+                                .required(false)
+                                .value_parser(clap::value_parser!(usize))
+                        )
+                        .arg(
+                            clap::arg!(-w --weighted "Choose the vendor's blocks by their number of addresses instead of uniformly")
+                                .required(false)
+                        )
+                        .arg(
+                            clap::arg!(--"ma-l-only" "Only choose from whole OUIs, no MA-M or MA-S blocks")
+                                .required(false)
+                        )
+                        .arg(
+                            clap::arg!(--"no-private" "Don't choose private blocks")
+                                .required(false)
+                        )
                         .arg(
                             clap::arg!([interface] ... "Change the MAC address for interface")
                                 .required(false)
@@ -261,6 +310,7 @@ fn build_cli() -> clap::Command {
                         .arg(
                             clap::arg!(<prefix> "MAC address prefix to use")
                                 .required(true)
+                                .value_parser(clap::value_parser!(OuiPrefix))
                                 .index(1)
                         )
                         .arg(
@@ -270,6 +320,20 @@ fn build_cli() -> clap::Command {
                                 .index(2)
                         )
                 )
+                .subcommand(
+                    clap::command!("local")
+                        .about("Generates a random locally administered unicast MAC address, like phones use")
+                        .arg(
+                            clap::arg!(-n --nibble <NIBBLE> "Second hex digit of the address: x2, x6, xA or xE, random if omitted")
+                                .required(false)
+                                .value_parser(macaddress::parse_local_nibble)
+                        )
+                        .arg(
+                            clap::arg!([interface] ... "Change the MAC address for interface")
+                                .required(false)
+                                .trailing_var_arg(true)
+                        )
+                )
                 .subcommand(
                     clap::command!("interface")
                         .about("Generates a random MAC address for the given interfaces")
@@ -284,6 +348,56 @@ fn build_cli() -> clap::Command {
                         )
                 )
         )
+        .subcommand(
+            clap::command!("vendors")
+                .about("Lists the vendors matching a query, best match first, with their number of prefixes")
+                .arg(
+                    clap::arg!([query] "Fuzzy matched vendor name, every vendor if omitted")
+                        .required(false)
+                )
+                .arg(
+                    clap::arg!(-r --regex "Match the query as a case insensitive regular expression")
+                        .required(false)
+                )
+        )
+        .subcommand(
+            clap::command!("lookup")
+                .about("Shows the vendor of MAC addresses, prefixes or interfaces")
+                .arg(
+                    clap::arg!(<address> ... "MAC addresses or prefixes in any notation, or interface names")
+                        .required(true)
+                        .trailing_var_arg(true)
+                )
+        )
+        .subcommand(
+            clap::command!("export")
+                .about("Writes the database as JSON")
+                .arg(
+                    clap::arg!([file] "File to write, standard output if omitted or -")
+                        .required(false)
+                )
+        )
+        .subcommand(
+            clap::command!("import")
+                .about("Replaces the database with a JSON export or a downloaded registry")
+                .arg(
+                    clap::arg!(<file> "File to read, - for standard input")
+                        .required(true)
+                )
+        )
+        .subcommand(
+            clap::command!("restore")
+                .about("Restores the original MAC address of the given interfaces")
+                .arg(
+                    clap::arg!(-p --permanent "Restore the permanent hardware address instead of the recorded one")
+                        .required(false)
+                )
+                .arg(
+                    clap::arg!([interface] ... "Interfaces to restore, all recorded interfaces if empty")
+                        .required(false)
+                        .trailing_var_arg(true)
+                )
+        )
         .arg(
             clap::arg!(--datasource <FILE> "Path to the datasource file")
                 .required(false)
@@ -292,175 +406,300 @@ fn build_cli() -> clap::Command {
             clap::arg!(--database <FILE> "Path to the database file")
                 .required(false)
         )
+        .arg(
+            clap::arg!(--state <FILE> "Path to the file holding the original MAC addresses")
+                .required(false)
+        )
+        .arg(
+            clap::arg!(--aliases <FILE> "Path to the file grouping vendor names under one company")
+                .required(false)
+        )
 }
 
-fn update(datasource: String, database: String) -> Result<(), String> {
+fn update(datasource: String, database: String) -> Result<(), Error> {
     println!("Updating database...");
 
-    let datasource = setup_datasource(&datasource);
-    let information = fetch(datasource, &database, false)?;
+    let datasources = load_datasource(&datasource)?;
+    let configured = configured_urls(&datasources);
+    let addr_database = match AddressDatabase::update(datasources, database)? {
+        Some(addr_database) => addr_database,
+        None => {
+            println!("Database is already up to date!");
+            return Ok(());
+        }
+    };
 
-    let addr_database = AddressDatabase::new(database, information);
-    addr_database.save()?;
+    println!("Database updated, found {} entries!", addr_database.len());
+    print_fallback(&datasource, &configured, &addr_database);
 
-    println!("Database updated, found {} entries!", addr_database.information.len());
+    let mut sources: BTreeMap<String, usize> = BTreeMap::new();
+    for info in addr_database.records() {
+        *sources.entry(info.source().unwrap_or_default()).or_default() += 1;
+    }
+    if sources.len() > 1 {
+        for (source, count) in sources {
+            println!("  {}: {} entries", source, count);
+        }
+    }
 
     return Ok(());
 }
 
-fn random_interface(database: &AddressDatabase, interface: Vec<String>, update: bool) {
-    println!("Generating random MAC address for interface {}...", interface.join(", "));
-    for interface in interface {
-        let mac = match mac_address::mac_address_by_name(&interface) {
-            Ok(mac) => mac,
-            Err(err) => {
-                println!("Failed to get MAC address for interface {}: {}", interface, err);
-                continue;
-            }
+fn export(datasource: String, database: String, file: &str) -> Result<(), Error> {
+    let addr_database = setup_data(datasource, database)?;
+
+    if file == "-" {
+        let json = addr_database.export()?;
+        let mut stdout = io::stdout().lock();
+        return match stdout.write_all(json.as_bytes()).and_then(|_| stdout.write_all(b"\n")).and_then(|_| stdout.flush()) {
+            Ok(_) => Ok(()),
+            // `export - | head` closes the pipe early, that isn't an error
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            Err(err) => Err(Error::io(String::from("Failed to write to standard output"), err))
         };
+    }
+
+    addr_database.export_to(file)?;
+    println!("Exported {} entries to {}", addr_database.len(), file);
+    return Ok(());
+}
+
+/// Replaces the database with `file`, either a JSON export or a registry in the format of the datasource.
+fn import(datasource: String, database: String, file: &str) -> Result<(), Error> {
+    let content = if file == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(file)
+    };
+    let content = match content {
+        Ok(content) => content,
+        Err(err) => return Err(Error::io(format!("Failed to read {:?}", file), err))
+    };
+
+    let datasource = load_datasource(&datasource)?;
+    let addr_database = AddressDatabase::import(&datasource, database, content)?;
+    println!("Database imported, found {} entries!", addr_database.len());
+    return Ok(());
+}
 
-        let mac = match mac {
-            Some(mac) => mac,
-            None => {
-                println!("No MAC address found for interface {}!", interface);
-                continue;
+/// Picks the vendor `query` refers to, listing the candidates when it is ambiguous.
+fn resolve_vendor<'a>(vendors: &'a [VendorMatch], query: &str, pick: Option<usize>) -> Result<&'a VendorMatch, Error> {
+    let resolved = vendor::resolve(vendors, query, pick);
+    if resolved.is_err() && pick.is_none() && vendors.len() > 1 {
+        println!("Several vendors match {}, choose one with --pick:", query);
+        print_vendors(&vendors[..vendors.len().min(10)]);
+        if vendors.len() > 10 {
+            println!("...and {} more, see `random-mac vendors {:?}`", vendors.len() - 10, query);
+        }
+    }
+    return resolved;
+}
+
+fn print_vendors(vendors: &[VendorMatch]) {
+    let width = vendors.len().to_string().len();
+    for (index, vendor) in vendors.iter().enumerate() {
+        let count = vendor.positions.len();
+        println!("{:>width$}. {} ({} {})", index + 1, vendor.name, count, if count == 1 { "prefix" } else { "prefixes" }, width = width);
+        let others = vendor.spellings.iter()
+            .filter(|(spelling, _)| *spelling != vendor.name)
+            .map(|(spelling, _)| spelling.as_str())
+            .collect::<Vec<_>>();
+        if !others.is_empty() {
+            println!("{:>width$}  also {}", "", others.join(", "), width = width);
+        }
+    }
+}
+
+fn lookup(database: &AddressDatabase, addresses: Vec<String>) -> Result<(), Failure> {
+    let mut exit_code = None;
+    for (index, address) in addresses.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+
+        // An interface name takes precedence over a prefix that happens to look the same, like `abc`
+        let prefix = match interface::current_address(address) {
+            Ok(Some(mac)) => {
+                println!("{} ({})", mac, address);
+                OuiPrefix::from(mac)
+            },
+            _ => match address.parse::<OuiPrefix>() {
+                Ok(prefix) => {
+                    println!("{}", address);
+                    prefix
+                },
+                // Anything that isn't written like an address was meant as an interface
+                Err(_) if !is_hex_notation(address) => {
+                    let err = Error::InterfaceNotFound(format!("Interface '{}' doesn't exist", address));
+                    eprintln!("{}", err);
+                    exit_code.get_or_insert(err.exit_code());
+                    continue;
+                },
+                Err(err) => {
+                    eprintln!("{}: {}", address, err);
+                    exit_code.get_or_insert(err.exit_code());
+                    continue;
+                }
             }
-        }.to_string();
-
-        match database.lookup(&mac) {
-            Some(result) => {
-                let new_mac = result.random_from_prefix();
-                if update {
-                    match update_mac(&interface, &new_mac) {
-                        Ok(_) => println!("MAC address for interface {} changed to {}", interface, new_mac),
-                        Err(err) => println!("Failed to change MAC address for interface {}: {}", interface, err)
-                    }
-                } else {
-                    println!("MAC address for interface {}: {}", interface, new_mac)
+        };
+
+        match database.lookup(&prefix) {
+            Some(information) => {
+                println!("  Prefix:               {}", information.prefix());
+                println!("  Vendor:               {}", information.vendor());
+                println!("  Block type:           {}", information.block_type());
+                println!("  Private:              {}", yes_no(information.is_private()));
+                if let Some(address) = information.address() {
+                    println!("  Address:              {}", address);
+                }
+                if let Some(source) = information.source() {
+                    println!("  Source:               {}", source);
                 }
             },
-            None => println!("No registered vendor found for interface {}!", interface)
+            None => println!("  Vendor:               unknown")
+        }
+
+        // The flags live in the first octet, shorter prefixes don't carry them yet
+        if prefix.first_octet().is_some() {
+            println!("  Locally administered: {}", yes_no(prefix.is_locally_administered()));
+            println!("  Multicast:            {}", yes_no(prefix.is_multicast()));
         }
     }
+    return reported(exit_code);
+}
+
+#[inline]
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
 }
 
-fn update_mac_by_info(mac: &Box<dyn MacInformation>, interface: &str) {
-    let random_mac = mac.random_from_prefix();
-    match mac_address::mac_address_by_name(interface) {
-        Ok(mac) => {
-            if mac.is_none() {
-                println!("Interface '{}' doesn't exist, skipping...", interface);
-                return;
+/// Prints the planned addresses if `show`, with their warnings and why interfaces got none.
+///
+/// Fails with the exit code of the first interface without an address.
+fn report_plan(plan: &[PlannedAddress], show: bool) -> Result<(), Failure> {
+    let mut exit_code = None;
+    for planned in plan {
+        match &planned.address {
+            Ok(address) => {
+                if show {
+                    println!("MAC address for interface {}: {}", planned.interface, address);
+                }
+                print_warnings(address, &planned.warnings);
+            },
+            Err(error) => {
+                eprintln!("Failed to generate MAC address for {}: {}", planned.interface, error);
+                exit_code.get_or_insert(error.exit_code());
             }
-        },
-        Err(_) => {
-            println!("Failed to get MAC address of {}, skipping!", interface);
-            return;
         }
     }
-    match update_mac(&interface, &random_mac) {
-        Ok(_) => println!("Updated MAC address of {} to {}", interface, random_mac),
-        Err(error) => println!("Failed to update MAC address of {}: {}", interface, error)
-    }
+    return reported(exit_code);
 }
 
-fn update_mac(interface: &str, mac: &str) -> Result<(), String> {
-    let turn_off = Command::new("ip")
-        .arg("link")
-        .arg("set")
-        .arg("dev")
-        .arg(interface)
-        .arg("down")
-        .output();
-
-    match turn_off {
-        Ok(_) => (),
-        Err(_) => return Err(format!("Failed to turn off interface {}!", interface))
+fn print_warnings(mac: &MacAddress, warnings: &[AddressIssue]) {
+    for warning in warnings {
+        println!("Warning: {} is {}", mac, warning);
     }
+}
 
-    let change = Command::new("ip")
-        .arg("link")
-        .arg("set")
-        .arg("dev")
-        .arg(interface)
-        .arg("address")
-        .arg(mac)
-        .output();
+/// Changes the interfaces of `plan`, unless one of them has no address.
+///
+/// A failing interface doesn't stop the others, the exit code is the one of the first failure.
+fn apply_plan(plan: Vec<PlannedAddress>, state: &str) -> Result<(), Failure> {
+    if let Err(failure) = report_plan(&plan, false) {
+        eprintln!("No interface was changed!");
+        return Err(failure);
+    }
 
-    match change {
-        Ok(_) => (),
-        Err(_) => return Err(format!("Failed to change MAC address for interface {}!", interface))
+    let mut exit_code = None;
+    for (interface, result) in interface::apply_plan(plan, state)? {
+        match result {
+            Ok(address) => println!("Updated MAC address of {} to {}", interface, address),
+            Err(error) => {
+                eprintln!("Failed to update MAC address of {}: {}", interface, error);
+                exit_code.get_or_insert(error.exit_code());
+            }
+        }
     }
+    return reported(exit_code);
+}
 
-    let turn_on = Command::new("ip")
-        .arg("link")
-        .arg("set")
-        .arg("dev")
-        .arg(interface)
-        .arg("up")
-        .output();
-
-    match turn_on {
-        Ok(_) => Ok(()),
-        Err(_) => return Err(format!("Failed to turn on interface {}!", interface))
+fn restore(interfaces: &[String], permanent: bool, state: &str) -> Result<(), Failure> {
+    let restored = interface::restore(interfaces, permanent, state)?;
+    if restored.is_empty() {
+        println!("No original MAC addresses recorded!");
+        return Ok(());
     }
 
+    let mut exit_code = None;
+    for (interface, result) in restored {
+        match result {
+            Ok(original) => println!("Restored MAC address of {} to {}", interface, original),
+            Err(error) => {
+                eprintln!("Failed to restore MAC address of {}: {}", interface, error);
+                exit_code.get_or_insert(error.exit_code());
+            }
+        }
+    }
+    return reported(exit_code);
 }
 
-fn setup_data(datasource: String, database: String) -> Result<AddressDatabase, String> {
-    let datasource = setup_datasource(&datasource);
-
-    return if Path::new(&database).exists() {
-        let content = fs::read_to_string(&database)
-            .expect(&*format!("Failed to read {:?}!", database));
+/// Fails with the exit code of the first error a command already printed, if there was one.
+#[inline]
+fn reported(exit_code: Option<i32>) -> Result<(), Failure> {
+    return match exit_code {
+        Some(code) => Err(Failure::Reported(code)),
+        None => Ok(())
+    };
+}
 
-        match macaddress::convert(datasource.name, content) {
-            Ok(result) => Ok(AddressDatabase::new(database, result)),
-            Err(_) => return Err(String::from("Failed to parse JSON"))
+/// Reads `datasource`, telling which system registry was picked when the file had to be created.
+fn load_datasource(datasource: &str) -> Result<DataSources, Error> {
+    let created = !Path::new(datasource).exists();
+    let datasources = DataSources::load_or_default(datasource)?;
+    if let (true, Some(source)) = (created, datasources.sources().first()) {
+        if source.url() != macaddress::DEFAULT_URL {
+            println!("Using system registry {} ({}), edit {} to use another source", source.url(), source.name(), datasource);
         }
-    } else {
-        println!("Database not found, downloading...");
-        let information = fetch(datasource, &database, true)?;
-        let addr_database = AddressDatabase::new(database, information);
-        addr_database.save()?;
-        println!("Database downloaded, found {} entries!", addr_database.information.len());
-        Ok(addr_database)
     }
+    return Ok(datasources);
 }
 
-fn setup_datasource(path: &String) -> DataSource {
-    if !Path::new(path).exists() {
-        let datasource = DataSource {
-            url: String::from("https://maclookup.app/downloads/json-database/get-db"),
-            name: String::from("maclookupapp")
-        };
-        let serialize = serde_json::to_string(&datasource)
-            .expect("Failed to serialize default datasource!");
+fn setup_data(datasource: String, database: String) -> Result<AddressDatabase, Error> {
+    let datasources = load_datasource(&datasource)?;
 
-        fs::write(path, serialize)
-            .expect(&*format!("Failed to write default datasource: {:?}", path));
-
-        return datasource;
+    if Path::new(&database).exists() {
+        return AddressDatabase::load(datasources, database);
     }
 
-    DataSource::from_file(path.as_ref())
-        .expect("Failed to read datasource!")
+    let legacy = legacy_database(&database).filter(|legacy| Path::new(legacy).exists());
+    match &legacy {
+        Some(legacy) => println!("Converting {} to the binary database format...", legacy),
+        None => println!("Database not found, downloading...")
+    }
+    let configured = configured_urls(&datasources);
+    let addr_database = AddressDatabase::load(datasources, database)?;
+    if legacy.is_none() {
+        println!("Database downloaded, found {} entries!", addr_database.len());
+        print_fallback(&datasource, &configured, &addr_database);
+    }
+    return Ok(addr_database);
 }
 
-fn fetch(datasource: DataSource, database: &String, write: bool) -> Result<Vec<Box<dyn MacInformation>>, String> {
-    let information = datasource.fetch_information()?;
-    if write {
-        let serialize = match serde_json::to_string(&information) {
-            Ok(json) => json,
-            Err(_) => return Err(String::from("Failed to serialize JSON"))
-        };
+fn configured_urls(datasources: &DataSources) -> Vec<String> {
+    return datasources.sources().iter().map(|source| source.url().to_string()).collect();
+}
 
-        return match fs::write(database, serialize) {
-            Ok(_) => Ok(information),
-            Err(_) => Err(String::from("Failed to write JSON"))
-        };
+/// Tells when a new database was built from a registry installed by the distribution instead of `datasource`.
+fn print_fallback(datasource: &str, configured: &[String], database: &AddressDatabase) {
+    let origin = database.records().next().and_then(|record| record.source());
+    if let Some(origin) = origin.filter(|origin| !configured.contains(origin)) {
+        println!("Using system registry {} instead of {}, edit {} to use another source", origin, configured.join(", "), datasource);
     }
-    return Ok(information);
+}
+
+/// Whether `value` only holds hex digits, the separators of the address notations and a mask.
+fn is_hex_notation(value: &str) -> bool {
+    value.chars().all(|character| character.is_ascii_hexdigit() || ":-./".contains(character))
 }
 
 #[inline]
@@ -470,17 +709,44 @@ fn is_root() -> bool {
 }
 
 #[inline]
-fn datasource() -> String {
-    return format!("{}/{}", app_dir(), "datasource.json");
+fn datasource() -> Result<String, Error> {
+    return Ok(format!("{}/{}", app_dir()?, "datasource.json"));
+}
+
+#[inline]
+fn database() -> Result<String, Error> {
+    return Ok(format!("{}/{}", app_dir()?, "database.bin"));
 }
 
+/// Vendor names to group under one company, see `VendorAliases`.
 #[inline]
-fn database() -> String {
-    return format!("{}/{}", app_dir(), "database.json");
+fn aliases() -> Result<String, Error> {
+    return Ok(format!("{}/{}", app_dir()?, "aliases.json"));
 }
 
 #[inline]
-fn app_dir() -> String {
-    let user = BaseDirs::new().unwrap();
-    return format!("{}/{}", user.data_dir().to_str().unwrap(), "random-mac");
+fn state() -> Result<String, Error> {
+    return Ok(format!("{}/{}", app_dir()?, "state.json"));
+}
+
+/// The data directory of the app, created on first use. Only needed for paths that weren't given as options, so
+/// services without a home directory can still pass all of them.
+fn app_dir() -> Result<String, Error> {
+    let user = match BaseDirs::new() {
+        Some(user) => user,
+        None => return Err(Error::Other(String::from(
+            "No home directory found, pass --datasource, --database, --state and --aliases instead"
+        )))
+    };
+    let app_dir = match user.data_dir().to_str() {
+        Some(data_dir) => format!("{}/{}", data_dir, "random-mac"),
+        None => return Err(Error::Other(format!("The data directory {:?} isn't valid UTF-8", user.data_dir())))
+    };
+
+    if !Path::new(&app_dir).exists() {
+        if let Err(err) = fs::create_dir_all(&app_dir) {
+            return Err(Error::io(format!("Failed to create {:?}", app_dir), err));
+        }
+    }
+    return Ok(app_dir);
 }
@@ -0,0 +1,175 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::address::OuiPrefix;
+use crate::macaddress::{DataSource, MacInformation};
+
+/// How to pick the vendor when several datasources register the same prefix.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictRule {
+
+    /// The source with the highest `priority` wins, ties go to the one listed first.
+    #[default]
+    Priority,
+    /// The most descriptive, i.e. longest, vendor name wins.
+    LongestName,
+
+}
+
+/// A registry entry together with the datasource it was imported from.
+pub struct SourcedInformation {
+
+    origin: String,
+    information: Box<dyn MacInformation>,
+
+}
+
+impl MacInformation for SourcedInformation {
+
+    fn prefix(&self) -> OuiPrefix {
+        self.information.prefix()
+    }
+
+    fn vendor(&self) -> String {
+        self.information.vendor()
+    }
+
+    fn is_private(&self) -> bool {
+        self.information.is_private()
+    }
+
+    fn block_type(&self) -> String {
+        self.information.block_type()
+    }
+
+    fn source(&self) -> Option<String> {
+        Some(self.origin.clone())
+    }
+
+    fn address(&self) -> Option<String> {
+        self.information.address()
+    }
+
+}
+
+/// Merges the entries of every source into one list, resolving duplicate prefixes with `rule`.
+pub fn merge(fetched: Vec<(&DataSource, Vec<Box<dyn MacInformation>>)>, rule: ConflictRule) -> Vec<Box<dyn MacInformation>> {
+    let mut fetched = fetched;
+    fetched.sort_by_key(|(source, _)| Reverse(source.priority));
+
+    let mut result: Vec<Box<dyn MacInformation>> = Vec::new();
+    let mut positions: HashMap<OuiPrefix, usize> = HashMap::new();
+    for (datasource, information) in fetched {
+        for information in information {
+            let entry = Box::new(SourcedInformation {
+                origin: datasource.url(),
+                information
+            });
+
+            let key = entry.prefix();
+
+            match positions.get(&key) {
+                Some(position) => {
+                    let replace = match rule {
+                        ConflictRule::Priority => false,
+                        ConflictRule::LongestName => entry.vendor().len() > result[*position].vendor().len()
+                    };
+                    if replace {
+                        result[*position] = entry;
+                    }
+                },
+                None => {
+                    positions.insert(key, result.len());
+                    result.push(entry);
+                }
+            }
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::macaddress::{DataSource, MacInformation};
+    use crate::record::MacRecord;
+    use super::{merge, ConflictRule};
+
+    fn source(url: &str, priority: i32) -> DataSource {
+        DataSource::new(url, "ieee").with_priority(priority)
+    }
+
+    fn information(entries: &[(&str, &str)]) -> Vec<Box<dyn MacInformation>> {
+        entries.iter()
+            .map(|(prefix, vendor)| Box::new(MacRecord {
+                prefix: prefix.parse().unwrap(),
+                vendor: vendor.to_string(),
+                block_type: String::from("MA-L"),
+                private: false,
+                source: None,
+                address: None
+            }) as Box<dyn MacInformation>)
+            .collect()
+    }
+
+    /// Merges a short-named source listed first with a long-named source of higher priority.
+    fn merged(rule: ConflictRule) -> Vec<(String, String, Option<String>)> {
+        let first = source("first.csv", 0);
+        let second = source("second.csv", 10);
+        let fetched = vec![
+            (&first, information(&[("00:1B:21", "Intel"), ("00:00:01", "Xerox")])),
+            (&second, information(&[("00:1B:21", "Intel Corporate"), ("00:00:0C", "Cisco")]))
+        ];
+        let mut result = merge(fetched, rule).iter()
+            .map(|entry| (entry.prefix().to_string(), entry.vendor(), entry.source()))
+            .collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    fn entry(prefix: &str, vendor: &str, origin: &str) -> (String, String, Option<String>) {
+        (prefix.to_string(), vendor.to_string(), Some(origin.to_string()))
+    }
+
+    #[test]
+    fn highest_priority_wins() {
+        assert_eq!(merged(ConflictRule::Priority), vec![
+            entry("00:00:01", "Xerox", "first.csv"),
+            entry("00:00:0C", "Cisco", "second.csv"),
+            entry("00:1B:21", "Intel Corporate", "second.csv")
+        ]);
+    }
+
+    #[test]
+    fn first_listed_wins_a_tie() {
+        let first = source("first.csv", 0);
+        let second = source("second.csv", 0);
+        let fetched = vec![
+            (&first, information(&[("00:1B:21", "Intel")])),
+            (&second, information(&[("00:1B:21", "Intel Corporate")]))
+        ];
+        let result = merge(fetched, ConflictRule::Priority);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].vendor(), "Intel");
+    }
+
+    #[test]
+    fn longest_name_wins() {
+        let first = source("first.csv", 10);
+        let second = source("second.csv", 0);
+        let fetched = vec![
+            (&first, information(&[("00:1B:21", "Intel")])),
+            (&second, information(&[("00:1B:21", "Intel Corporate")]))
+        ];
+        let result = merge(fetched, ConflictRule::LongestName);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].vendor(), "Intel Corporate");
+        assert_eq!(result[0].source().as_deref(), Some("second.csv"));
+    }
+
+    #[test]
+    fn rules_are_read_in_kebab_case() {
+        assert_eq!(serde_json::from_str::<ConflictRule>("\"longest-name\"").unwrap(), ConflictRule::LongestName);
+        assert_eq!(serde_json::from_str::<ConflictRule>("\"priority\"").unwrap(), ConflictRule::Priority);
+    }
+}
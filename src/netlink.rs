@@ -0,0 +1,166 @@
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use crate::address::MacAddress;
+
+const NLMSG_HEADER_LEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const RTA_HEADER_LEN: usize = 4;
+const RECEIVE_BUFFER: usize = 8192;
+
+/// A rtnetlink (NETLINK_ROUTE) socket used to modify links without going through iproute2.
+pub struct NetlinkSocket {
+    fd: RawFd,
+    sequence: u32,
+}
+
+impl NetlinkSocket {
+
+    pub fn open() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        return Ok(Self { fd, sequence: 0 });
+    }
+
+    /// Sets or clears `IFF_UP` on the link, the equivalent of `ip link set dev <interface> up|down`.
+    pub fn set_link_up(&mut self, index: i32, up: bool) -> io::Result<()> {
+        let flags = if up { libc::IFF_UP as u32 } else { 0 };
+        return self.new_link(index, flags, libc::IFF_UP as u32, &[]);
+    }
+
+    /// Sends `RTM_NEWLINK` with `IFLA_ADDRESS`, the equivalent of `ip link set dev <interface> address <mac>`.
+    pub fn set_address(&mut self, index: i32, mac: &MacAddress) -> io::Result<()> {
+        return self.new_link(index, 0, 0, &[(libc::IFLA_ADDRESS, &mac.octets())]);
+    }
+
+    fn new_link(&mut self, index: i32, flags: u32, change: u32, attributes: &[(u16, &[u8])]) -> io::Result<()> {
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let mut message = Vec::with_capacity(NLMSG_HEADER_LEN + IFINFOMSG_LEN + 32);
+
+        // struct nlmsghdr, the length is patched in once the payload is known
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&libc::RTM_NEWLINK.to_ne_bytes());
+        message.extend_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_ACK) as u16).to_ne_bytes());
+        message.extend_from_slice(&self.sequence.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+
+        // struct ifinfomsg
+        message.push(libc::AF_UNSPEC as u8);
+        message.push(0);
+        message.extend_from_slice(&0u16.to_ne_bytes());
+        message.extend_from_slice(&index.to_ne_bytes());
+        message.extend_from_slice(&flags.to_ne_bytes());
+        message.extend_from_slice(&change.to_ne_bytes());
+
+        // struct rtattr for every attribute, each padded to a 4 byte boundary
+        for (kind, data) in attributes {
+            message.extend_from_slice(&((RTA_HEADER_LEN + data.len()) as u16).to_ne_bytes());
+            message.extend_from_slice(&kind.to_ne_bytes());
+            message.extend_from_slice(data);
+            message.resize(align(message.len()), 0);
+        }
+
+        let length = message.len() as u32;
+        message[0..4].copy_from_slice(&length.to_ne_bytes());
+
+        self.send(&message)?;
+        return self.acknowledge();
+    }
+
+    fn send(&self, message: &[u8]) -> io::Result<()> {
+        let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+        let sent = unsafe {
+            libc::sendto(
+                self.fd,
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        return Ok(());
+    }
+
+    /// Waits for the `NLMSG_ERROR` reply to the last request and turns its errno into an `io::Error`.
+    fn acknowledge(&self) -> io::Result<()> {
+        let mut buffer = vec![0u8; RECEIVE_BUFFER];
+        loop {
+            let received = unsafe {
+                libc::recv(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0)
+            };
+            if received < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+
+            let received = received as usize;
+            let mut offset = 0;
+            while offset + NLMSG_HEADER_LEN <= received {
+                let header = &buffer[offset..];
+                let length = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]) as usize;
+                let kind = u16::from_ne_bytes([header[4], header[5]]);
+                let sequence = u32::from_ne_bytes([header[8], header[9], header[10], header[11]]);
+                if length < NLMSG_HEADER_LEN || offset + length > received {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated netlink message"));
+                }
+
+                if sequence == self.sequence && kind == libc::NLMSG_ERROR as u16 {
+                    if length < NLMSG_HEADER_LEN + 4 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated netlink error"));
+                    }
+                    let payload = &header[NLMSG_HEADER_LEN..];
+                    let error = i32::from_ne_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                    return if error == 0 {
+                        Ok(())
+                    } else {
+                        Err(io::Error::from_raw_os_error(-error))
+                    };
+                }
+
+                offset += align(length);
+            }
+        }
+    }
+
+}
+
+impl Drop for NetlinkSocket {
+
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+
+}
+
+/// Resolves an interface name to its kernel index.
+pub fn interface_index(interface: &str) -> io::Result<i32> {
+    let name = match CString::new(interface) {
+        Ok(name) => name,
+        Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid interface name"))
+    };
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(index as i32);
+}
+
+#[inline]
+fn align(length: usize) -> usize {
+    (length + 3) & !3
+}
@@ -0,0 +1,180 @@
+//! Registry entries as stored in the database and its versioned JSON export.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::address::OuiPrefix;
+use crate::error::{Error, Result};
+use crate::macaddress::{self, MacInformation};
+
+/// Version of the JSON schema written by `export`.
+///
+/// 0. The maclookup.app entries as downloaded, the `database.json` of versions before the schema.
+/// 1. `{"version": 1, "records": [...]}` of `MacRecord`.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// A registry entry as stored in the database, independent of the datasource it was imported from.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MacRecord {
+
+    pub(crate) prefix: OuiPrefix,
+    pub(crate) vendor: String,
+    pub(crate) block_type: String,
+    pub(crate) private: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<String>,
+    /// Postal address of the organization, see `MacInformation::address`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) address: Option<String>,
+
+}
+
+impl MacInformation for MacRecord {
+
+    fn prefix(&self) -> OuiPrefix {
+        self.prefix
+    }
+
+    fn vendor(&self) -> String {
+        self.vendor.clone()
+    }
+
+    fn is_private(&self) -> bool {
+        self.private
+    }
+
+    fn block_type(&self) -> String {
+        self.block_type.clone()
+    }
+
+    fn source(&self) -> Option<String> {
+        self.source.clone()
+    }
+
+    fn address(&self) -> Option<String> {
+        self.address.clone()
+    }
+
+}
+
+impl From<&dyn MacInformation> for MacRecord {
+
+    fn from(information: &dyn MacInformation) -> Self {
+        MacRecord {
+            prefix: information.prefix(),
+            vendor: information.vendor(),
+            block_type: information.block_type(),
+            private: information.is_private(),
+            source: information.source(),
+            address: information.address()
+        }
+    }
+
+}
+
+#[derive(Serialize)]
+struct RecordFile<'a> {
+
+    version: u64,
+    records: &'a [MacRecord],
+
+}
+
+/// Serializes `records` in the current schema.
+pub fn to_json(records: &[MacRecord]) -> Result<String> {
+    let file = RecordFile { version: SCHEMA_VERSION, records };
+    return match serde_json::to_string_pretty(&file) {
+        Ok(json) => Ok(json),
+        Err(_) => Err(Error::Other(String::from("Failed to serialize JSON")))
+    };
+}
+
+/// Reads records of any schema version, migrating older ones to the current schema.
+pub fn from_json(data: &str) -> Result<Vec<MacRecord>> {
+    let value: Value = match serde_json::from_str(data) {
+        Ok(value) => value,
+        Err(err) => return Err(Error::json("JSON", &err))
+    };
+
+    let mut file = match value {
+        Value::Object(file) => file,
+        Value::Array(_) => return migrate_maclookupapp(data),
+        _ => return Err(Error::parse("JSON", "expected an export or an array of maclookup.app entries"))
+    };
+
+    let version = match file.get("version").and_then(|version| version.as_u64()) {
+        Some(version) => version,
+        None => return Err(Error::parse("JSON", "missing schema version"))
+    };
+    if version != SCHEMA_VERSION {
+        return Err(Error::parse("JSON", format!("unsupported schema version {}, the supported version is {}", version, SCHEMA_VERSION)));
+    }
+
+    let records = file.remove("records").unwrap_or(Value::Array(Vec::new()));
+    return match serde_json::from_value(records) {
+        Ok(records) => Ok(records),
+        Err(err) => Err(Error::json(format!("records of schema version {}", SCHEMA_VERSION), &err))
+    };
+}
+
+/// Version 0 is the maclookup.app database, the only datasource there was.
+fn migrate_maclookupapp(data: &str) -> Result<Vec<MacRecord>> {
+    let information = match macaddress::convert(String::from("maclookupapp"), data.to_string()) {
+        Ok(information) => information,
+        Err(Error::Parse { line, column, message, .. }) => return Err(Error::Parse {
+            what: String::from("records of schema version 0"),
+            line,
+            column,
+            message
+        }),
+        Err(err) => return Err(err)
+    };
+    return Ok(information.iter()
+        .map(|information| MacRecord::from(information.as_ref()))
+        .collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::macaddress::MacInformation;
+    use super::{from_json, to_json};
+
+    /// A `database.json` as written before the schema, the maclookup.app download as is.
+    const BASELINE: &str = r#"[
+        {"macPrefix": "00:1B:21", "vendorName": "Intel Corporate", "private": false, "blockType": "MA-L"},
+        {"macPrefix": "8C:1F:64:F2:1", "vendorName": "Private", "private": true, "blockType": "MA-S"}
+    ]"#;
+
+    #[test]
+    fn baseline_database_is_migrated() {
+        let records = from_json(BASELINE).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].prefix().to_string(), "00:1B:21");
+        assert_eq!(records[0].vendor(), "Intel Corporate");
+        assert_eq!(records[0].block_type(), "MA-L");
+        assert!(!records[0].is_private());
+        assert!(records[1].is_private());
+        assert_eq!(records[1].source(), None);
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut records = from_json(BASELINE).unwrap();
+        records[0].source = Some(String::from("https://standards-oui.ieee.org/oui/oui.csv"));
+        records[0].address = Some(String::from("Kulim"));
+
+        let json = to_json(&records).unwrap();
+        assert!(json.contains("\"version\": 1"));
+        let read = from_json(&json).unwrap();
+        assert_eq!(to_json(&read).unwrap(), json);
+        assert_eq!(read[0].address().as_deref(), Some("Kulim"));
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        assert!(from_json(r#"{"version": 2, "records": []}"#).is_err());
+        assert!(from_json(r#"{"records": []}"#).is_err());
+        assert!(from_json(r#"[{"prefix": "00:1B:21", "vendor": "Intel"}]"#).is_err());
+        assert!(from_json("\"00:1B:21\"").is_err());
+    }
+}
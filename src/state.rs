@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::address::MacAddress;
+use crate::error::{Error, Result};
+use crate::storage;
+
+/// Addresses the interfaces had before `random-mac` changed them for the first time.
+#[derive(Serialize, Deserialize, Default)]
+pub struct InterfaceState {
+
+    #[serde(skip)]
+    path: String,
+    original: BTreeMap<String, MacAddress>,
+
+}
+
+impl InterfaceState {
+
+    pub fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Self { path: path.to_string(), ..Default::default() });
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(Error::io(format!("Failed to read {:?}", path), err))
+        };
+
+        let mut state: InterfaceState = match serde_json::from_str(content.as_str()) {
+            Ok(json) => json,
+            Err(err) => return Err(Error::json(format!("{:?}", path), &err))
+        };
+        state.path = path.to_string();
+        return Ok(state);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let serialize = match serde_json::to_string_pretty(&self) {
+            Ok(json) => json,
+            Err(_) => return Err(Error::Other(String::from("Failed to serialize JSON")))
+        };
+
+        return match storage::write_atomic(&self.path, serialize.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::io(format!("Failed to write {:?}", self.path), err))
+        };
+    }
+
+    /// Remembers `mac` as the original address of `interface`, unless one is already recorded.
+    pub fn record(&mut self, interface: &str, mac: MacAddress) -> bool {
+        if self.original.contains_key(interface) {
+            return false;
+        }
+        self.original.insert(interface.to_string(), mac);
+        return true;
+    }
+
+    pub fn original(&self, interface: &str) -> Option<MacAddress> {
+        self.original.get(interface).copied()
+    }
+
+    pub fn forget(&mut self, interface: &str) -> Option<MacAddress> {
+        self.original.remove(interface)
+    }
+
+    pub fn interfaces(&self) -> Vec<String> {
+        self.original.keys().cloned().collect()
+    }
+
+}
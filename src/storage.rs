@@ -0,0 +1,111 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use crate::error::{Error, Result};
+
+/// Replaces `path` with `contents` so readers see either the old or the new file, never a partial one.
+///
+/// The data goes to a temporary file in the same directory, is fsynced and then renamed over `path`.
+pub fn write_atomic(path: &str, contents: &[u8]) -> io::Result<()> {
+    let target = Path::new(path);
+    let directory = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new(".")
+    };
+    let name = match target.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid file path"))
+    };
+    let temporary = directory.join(format!(".{}.{}.tmp", name, std::process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temporary, target)?;
+        // Persist the rename itself
+        File::open(directory)?.sync_all()
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    return result;
+}
+
+/// Advisory `flock` on `<path>.lock`, released when dropped.
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+
+    /// Waits for a lock that excludes every other reader and writer, taken for updates.
+    pub fn exclusive(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(format!("{}.lock", path))?;
+        return Self::acquire(file, libc::LOCK_EX);
+    }
+
+    /// Waits for a lock that only excludes writers, taken while reading.
+    ///
+    /// `flock` works on read-only files, so users who can't write next to `path` can still read it. They go without
+    /// a lock when the lock file doesn't exist yet and they can't create it either.
+    pub fn shared(path: &str) -> io::Result<Option<Self>> {
+        let lock_path = format!("{}.lock", path);
+        let file = match File::open(&lock_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                match OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path) {
+                    Ok(file) => file,
+                    Err(err) if is_read_only(&err) => return Ok(None),
+                    Err(err) => return Err(err)
+                }
+            },
+            Err(err) if is_read_only(&err) => return Ok(None),
+            Err(err) => return Err(err)
+        };
+        return Self::acquire(file, libc::LOCK_SH).map(Some);
+    }
+
+    fn acquire(file: File, operation: libc::c_int) -> io::Result<Self> {
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(Self { file });
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+
+}
+
+impl Drop for FileLock {
+
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+
+}
+
+/// Whether `error` says the lock file can't be created or opened by this user.
+fn is_read_only(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::EACCES) | Some(libc::EROFS))
+}
+
+/// Takes the advisory lock of `path`, exclusive for writers and shared for readers.
+///
+/// Readers get `None` when they can't lock, see `FileLock::shared`.
+pub fn lock(path: &str, exclusive: bool) -> Result<Option<FileLock>> {
+    let lock = if exclusive { FileLock::exclusive(path).map(Some) } else { FileLock::shared(path) };
+    return match lock {
+        Ok(lock) => Ok(lock),
+        Err(err) => Err(Error::io(format!("Failed to lock {:?}", path), err))
+    };
+}
@@ -0,0 +1,393 @@
+//! Finding vendors by name and choosing among their blocks.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use regex::{Regex, RegexBuilder};
+use crate::error::{Error, Result};
+use crate::macaddress::MacInformation;
+use crate::record::MacRecord;
+
+/// How `vendors` and `random vendor` match vendor names.
+pub enum VendorQuery {
+
+    /// Every vendor, ranked by their number of prefixes.
+    All,
+    /// Ranks vendors by how well the name matches, like the fuzzy finders of editors and shells.
+    Fuzzy(String),
+    /// Case insensitive regular expression, matching vendors are ranked by their number of prefixes.
+    Pattern(Regex),
+
+}
+
+/// A company with the positions of every block registered to it, under any of its spellings.
+pub struct VendorMatch {
+
+    /// The alias of the company, or else its most used spelling.
+    pub name: String,
+    /// Every spelling of the vendor in the database with its number of blocks, most used first.
+    pub spellings: Vec<(String, usize)>,
+    /// Positions of the company's blocks in the database, see `AddressDatabase::record`.
+    pub positions: Vec<usize>,
+    key: String,
+    score: i64,
+
+}
+
+impl VendorMatch {
+
+    /// Whether this is exactly the company `query` names, ignoring case, punctuation and corporate suffixes.
+    pub fn is_named(&self, query: &str) -> bool {
+        self.key == normalize(query)
+    }
+
+}
+
+/// Company names of vendors that are spelled too differently to be grouped by `normalize`, read from `aliases.json`.
+///
+/// ```json
+/// {"HP": ["Hewlett Packard", "Hewlett-Packard Company", "HP Inc."]}
+/// ```
+#[derive(Default)]
+pub struct VendorAliases {
+
+    names: HashMap<String, String>,
+
+}
+
+impl VendorAliases {
+
+    /// Reads the aliases at `path`, a missing file means there are none.
+    pub fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(Error::io(format!("Failed to read {:?}", path), err))
+        };
+
+        let aliases: BTreeMap<String, Vec<String>> = match serde_json::from_str(content.as_str()) {
+            Ok(json) => json,
+            Err(err) => return Err(Error::json(format!("{:?}", path), &err))
+        };
+
+        let mut names = HashMap::new();
+        for (name, vendors) in aliases {
+            names.insert(normalize(&name), name.clone());
+            for vendor in vendors {
+                names.insert(normalize(&vendor), name.clone());
+            }
+        }
+        return Ok(Self { names });
+    }
+
+    /// The alias `vendor` belongs to, if any.
+    pub fn alias(&self, vendor: &str) -> Option<&String> {
+        self.names.get(&normalize(vendor))
+    }
+
+}
+
+/// Reduces a vendor name to the company, `Intel Corporate`, `Intel Corp.` and `INTEL CORPORATION` are all `intel`.
+///
+/// Case and punctuation are dropped and legal forms are stripped from the end, the first word is always kept.
+pub fn normalize(vendor: &str) -> String {
+    let cleaned = vendor.to_lowercase()
+        .chars()
+        .map(|character| if character.is_alphanumeric() { character } else { ' ' })
+        .collect::<String>();
+    let mut words = cleaned.split_whitespace().collect::<Vec<_>>();
+    while words.len() > 1 && CORPORATE_SUFFIXES.contains(words.last().unwrap()) {
+        words.pop();
+    }
+    return words.join(" ");
+}
+
+/// Legal forms and similar filler that registries append to company names.
+const CORPORATE_SUFFIXES: [&str; 34] = [
+    "inc", "incorporated", "corp", "corporation", "corporate", "co", "company", "companies", "ltd", "limited",
+    "llc", "llp", "lp", "plc", "gmbh", "mbh", "ag", "kg", "se", "sa", "sas", "sarl", "srl", "spa", "bv", "nv",
+    "oy", "ab", "as", "asa", "pty", "pte", "kk", "group",
+];
+
+impl VendorQuery {
+
+    /// A fuzzy query, or a regular expression with `regex`. Without a query every vendor matches.
+    pub fn new(query: Option<&str>, regex: bool) -> Result<Self> {
+        let query = match query {
+            Some(query) if !query.is_empty() => query,
+            _ => return Ok(VendorQuery::All)
+        };
+        if !regex {
+            return Ok(VendorQuery::Fuzzy(query.to_string()));
+        }
+        return match RegexBuilder::new(query).case_insensitive(true).build() {
+            Ok(pattern) => Ok(VendorQuery::Pattern(pattern)),
+            Err(err) => Err(Error::parse("regular expression", err.to_string()))
+        };
+    }
+
+    fn score(&self, matcher: &SkimMatcherV2, vendor: &str) -> Option<i64> {
+        return match self {
+            VendorQuery::All => Some(0),
+            VendorQuery::Fuzzy(query) => matcher.fuzzy_match(vendor, query),
+            VendorQuery::Pattern(pattern) => pattern.is_match(vendor).then_some(0)
+        };
+    }
+
+}
+
+struct Group<'a> {
+
+    alias: Option<String>,
+    spellings: HashMap<&'a str, usize>,
+    positions: Vec<usize>,
+
+}
+
+/// Groups the `(position, vendor)` pairs by company and ranks the ones matching `query`, best first.
+///
+/// Vendors are grouped by their alias or else their normalized name, a group matches if any of its spellings does.
+pub fn search<'a>(vendors: impl Iterator<Item = (usize, &'a str)>, query: &VendorQuery, aliases: &VendorAliases) -> Vec<VendorMatch> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut groups: HashMap<&str, (String, Option<String>)> = HashMap::new();
+    let mut matches: HashMap<String, Group> = HashMap::new();
+
+    for (position, vendor) in vendors {
+        let (key, alias) = groups.entry(vendor).or_insert_with(|| {
+            let alias = aliases.alias(vendor).cloned();
+            let key = match &alias {
+                Some(alias) => normalize(alias),
+                None => normalize(vendor)
+            };
+            (key, alias)
+        });
+        let group = matches.entry(key.clone())
+            .or_insert_with(|| Group { alias: alias.clone(), spellings: HashMap::new(), positions: Vec::new() });
+        *group.spellings.entry(vendor).or_default() += 1;
+        group.positions.push(position);
+    }
+
+    let mut result = Vec::new();
+    for (key, Group { alias, spellings, positions }) in matches {
+        let score = spellings.keys()
+            .map(|spelling| spelling.to_string())
+            .chain(alias.clone())
+            .filter_map(|name| query.score(&matcher, &name))
+            .max();
+        let score = match score {
+            Some(score) => score,
+            None => continue
+        };
+
+        let mut spellings = spellings.into_iter()
+            .map(|(spelling, count)| (spelling.to_string(), count))
+            .collect::<Vec<_>>();
+        spellings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let name = alias.unwrap_or_else(|| spellings[0].0.clone());
+        result.push(VendorMatch { name, spellings, positions, key, score });
+    }
+
+    result.sort_by(|a, b| b.score.cmp(&a.score)
+        .then(b.positions.len().cmp(&a.positions.len()))
+        .then(a.name.cmp(&b.name)));
+    return result;
+}
+
+/// Restricts which of a vendor's blocks `random vendor` may draw from.
+#[derive(Default)]
+pub struct BlockFilter {
+
+    /// Only whole OUIs, no MA-M or MA-S blocks.
+    pub large_only: bool,
+    /// No blocks of organizations that keep their name private.
+    pub exclude_private: bool,
+
+}
+
+impl BlockFilter {
+
+    /// Whether `random vendor` may draw from `record`.
+    pub fn accepts(&self, record: &MacRecord) -> bool {
+        if self.large_only && !record.block_type().eq_ignore_ascii_case("MA-L") {
+            return false;
+        }
+        return !(self.exclude_private && record.is_private());
+    }
+
+}
+
+/// Picks the vendor `query` refers to out of its `vendors` matches, best match first.
+///
+/// `pick` chooses one of them by its 1-based rank. Otherwise the vendor has to be unambiguous: named exactly like
+/// `query` or the only match.
+pub fn resolve<'a>(vendors: &'a [VendorMatch], query: &str, pick: Option<usize>) -> Result<&'a VendorMatch> {
+    if vendors.is_empty() {
+        return Err(Error::Other(format!("No vendor found with name {}!", query)));
+    }
+
+    if let Some(pick) = pick {
+        return match pick.checked_sub(1).and_then(|index| vendors.get(index)) {
+            Some(vendor) => Ok(vendor),
+            None => Err(Error::Other(format!("Invalid choice {}, {} vendors match {}", pick, vendors.len(), query)))
+        };
+    }
+
+    if let Some(vendor) = vendors.iter().find(|vendor| vendor.is_named(query)) {
+        return Ok(vendor);
+    }
+    if vendors.len() == 1 {
+        return Ok(&vendors[0]);
+    }
+    return Err(Error::Other(format!("Ambiguous vendor name, {} vendors match {}", vendors.len(), query)));
+}
+
+/// Draws one of `records`, either each block with the same chance or in proportion to its number of addresses.
+pub fn choose_block(records: &[MacRecord], weighted: bool) -> Result<&MacRecord> {
+    let mut rng = rand::thread_rng();
+    if !weighted {
+        return match records.choose(&mut rng) {
+            Some(record) => Ok(record),
+            None => Err(Error::Other(String::from("No block to choose from")))
+        };
+    }
+
+    // An MA-L block holds 2^24 addresses, an MA-S block only 2^12
+    let weights = records.iter().map(|record| record.prefix().block_size());
+    return match WeightedIndex::new(weights) {
+        Ok(distribution) => Ok(&records[distribution.sample(&mut rng)]),
+        Err(err) => Err(Error::Other(format!("Failed to weigh the blocks: {}", err)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{normalize, resolve, search, VendorAliases, VendorMatch, VendorQuery};
+
+    const VENDORS: [&str; 5] = [
+        "Intel Corporate",
+        "Intel Corporate",
+        "Intel Wireless Network Group",
+        "Cisco Systems, Inc",
+        "Cisco Systems, Inc",
+    ];
+
+    fn matches(query: VendorQuery, aliases: &VendorAliases) -> Vec<VendorMatch> {
+        search(VENDORS.iter().copied().enumerate(), &query, aliases)
+    }
+
+    fn names(vendors: &[VendorMatch]) -> Vec<&str> {
+        vendors.iter().map(|vendor| vendor.name.as_str()).collect()
+    }
+
+    #[test]
+    fn fuzzy_search_ranks_matches() {
+        let vendors = matches(VendorQuery::new(Some("intel"), false).unwrap(), &VendorAliases::default());
+        let mut found = names(&vendors);
+        found.sort();
+        assert_eq!(found, vec!["Intel Corporate", "Intel Wireless Network Group"]);
+
+        let vendors = matches(VendorQuery::new(Some("intel corp"), false).unwrap(), &VendorAliases::default());
+        assert_eq!(vendors[0].name, "Intel Corporate");
+        assert_eq!(vendors[0].positions, vec![0, 1]);
+    }
+
+    #[test]
+    fn every_vendor_ranked_by_prefixes() {
+        let vendors = matches(VendorQuery::new(None, false).unwrap(), &VendorAliases::default());
+        assert_eq!(names(&vendors), vec!["Cisco Systems, Inc", "Intel Corporate", "Intel Wireless Network Group"]);
+        assert_eq!(vendors[0].positions, vec![3, 4]);
+    }
+
+    #[test]
+    fn regex_search_is_case_insensitive() {
+        let vendors = matches(VendorQuery::new(Some("^INTEL W"), true).unwrap(), &VendorAliases::default());
+        assert_eq!(names(&vendors), vec!["Intel Wireless Network Group"]);
+        assert!(VendorQuery::new(Some("(unclosed"), true).is_err());
+    }
+
+    #[test]
+    fn resolve_needs_an_unambiguous_vendor() {
+        let vendors = matches(VendorQuery::new(Some("int"), false).unwrap(), &VendorAliases::default());
+        assert_eq!(vendors.len(), 2);
+        assert!(resolve(&vendors, "int", None).is_err());
+        assert!(resolve(&[], "nobody", None).is_err());
+
+        // Named exactly like the query, up to the legal form
+        assert_eq!(resolve(&vendors, "Intel Corp.", None).unwrap().name, "Intel Corporate");
+
+        let regex = matches(VendorQuery::new(Some("wireless"), true).unwrap(), &VendorAliases::default());
+        assert_eq!(resolve(&regex, "wireless", None).unwrap().name, "Intel Wireless Network Group");
+    }
+
+    fn aliases(aliases: &[(&str, &[&str])]) -> VendorAliases {
+        let mut names = HashMap::new();
+        for (name, vendors) in aliases {
+            names.insert(normalize(name), name.to_string());
+            for vendor in vendors.iter() {
+                names.insert(normalize(vendor), name.to_string());
+            }
+        }
+        VendorAliases { names }
+    }
+
+    #[test]
+    fn names_are_reduced_to_the_company() {
+        for name in ["Intel Corporate", "Intel Corp.", "INTEL CORPORATION", "intel, inc"] {
+            assert_eq!(normalize(name), "intel", "{}", name);
+        }
+        assert_eq!(normalize("HUAWEI TECHNOLOGIES CO.,LTD"), "huawei technologies");
+        assert_eq!(normalize("Siemens AG"), "siemens");
+        // The first word stays even if it looks like a legal form
+        assert_eq!(normalize("Group Inc."), "group");
+    }
+
+    #[test]
+    fn spellings_are_grouped() {
+        let spellings = ["Intel Corporate", "INTEL CORPORATION", "Intel Corporate", "Intel Corp."];
+        let vendors = search(spellings.iter().copied().enumerate(), &VendorQuery::All, &VendorAliases::default());
+        assert_eq!(vendors.len(), 1);
+        assert_eq!(vendors[0].name, "Intel Corporate");
+        assert_eq!(vendors[0].positions, vec![0, 1, 2, 3]);
+        assert_eq!(vendors[0].spellings, vec![
+            (String::from("Intel Corporate"), 2),
+            (String::from("INTEL CORPORATION"), 1),
+            (String::from("Intel Corp."), 1)
+        ]);
+    }
+
+    #[test]
+    fn aliases_group_differently_spelled_companies() {
+        let aliases = aliases(&[("HP", &["Hewlett Packard", "Hewlett-Packard Company"])]);
+        assert_eq!(aliases.alias("HEWLETT PACKARD").map(|alias| alias.as_str()), Some("HP"));
+        assert_eq!(aliases.alias("Intel Corporate"), None);
+
+        let spellings = ["Hewlett Packard", "Hewlett-Packard Company", "HP Inc.", "Intel Corporate"];
+        let vendors = search(spellings.iter().copied().enumerate(), &VendorQuery::All, &aliases);
+        assert_eq!(vendors[0].name, "HP");
+        assert_eq!(vendors[0].positions, vec![0, 1, 2]);
+
+        // A group matches by its alias as well as by any spelling
+        for query in ["hewlett", "HP"] {
+            let vendors = search(spellings.iter().copied().enumerate(), &VendorQuery::new(Some(query), false).unwrap(), &aliases);
+            assert_eq!(vendors[0].name, "HP", "{}", query);
+            assert!(vendors[0].is_named("HP"));
+        }
+    }
+
+    #[test]
+    fn pick_chooses_by_rank() {
+        let vendors = matches(VendorQuery::new(Some("int"), false).unwrap(), &VendorAliases::default());
+        assert_eq!(resolve(&vendors, "int", Some(1)).unwrap().name, vendors[0].name);
+        assert_eq!(resolve(&vendors, "int", Some(2)).unwrap().name, vendors[1].name);
+        assert!(resolve(&vendors, "int", Some(0)).is_err());
+        assert!(resolve(&vendors, "int", Some(3)).is_err());
+    }
+}